@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::effect::Effect;
+use crate::stereo::balance_gains;
+
+pub type ParameterMap = HashMap<String, Vec<String>>;
+
+pub struct AutoPan {
+    pub bypassing: bool,
+    rate: f32,
+    depth: f32,
+    phase: f32,
+    sample_rate: f32,
+}
+
+impl AutoPan {
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.max(0.0);
+    }
+
+    pub fn set_depth(&mut self, depth: f32) {
+        self.depth = depth.clamp(0.0, 1.0);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+}
+
+impl Effect for AutoPan {
+    fn new() -> Self {
+        AutoPan {
+            bypassing: false,
+            rate: 1.0,
+            depth: 1.0,
+            phase: 0.0,
+            sample_rate: 48000.0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "autopan"
+    }
+
+    fn set_params(&mut self, params: &ParameterMap) {
+        if let Some(params) = params.get("autopan") {
+            for param in params {
+                let param_parts: Vec<&str> = param.split(' ').collect();
+                match param_parts[..] {
+                    ["rate", rate] => {
+                        if let Ok(rate) = rate.parse() {
+                            self.set_rate(rate);
+                        }
+                    }
+                    ["depth", depth] => {
+                        if let Ok(depth) = depth.parse() {
+                            self.set_depth(depth);
+                        }
+                    }
+                    ["bypass", bypass] => {
+                        if let Ok(bypass) = bypass.parse() {
+                            self.bypassing = bypass;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn process_samples(
+        &mut self,
+        input_l: Option<&[f32]>,
+        input_r: Option<&[f32]>,
+        output_l: Option<&mut [f32]>,
+        output_r: Option<&mut [f32]>,
+    ) {
+        if self.bypassing {
+            if let Some(input_l) = input_l {
+                if let Some(output_l) = output_l {
+                    output_l.clone_from_slice(input_l);
+                }
+            }
+            if let Some(input_r) = input_r {
+                if let Some(output_r) = output_r {
+                    output_r.clone_from_slice(input_r);
+                }
+            }
+            return;
+        }
+
+        if let (Some(input_l), Some(input_r), Some(output_l), Some(output_r)) =
+            (input_l, input_r, output_l, output_r)
+        {
+            let phase_inc = 2.0 * PI * self.rate / self.sample_rate;
+            for index in 0..input_l.len() {
+                let (gain_l, gain_r) = balance_gains(self.depth * self.phase.sin());
+                output_l[index] = gain_l * input_l[index];
+                output_r[index] = gain_r * input_r[index];
+                self.phase = (self.phase + phase_inc) % (2.0 * PI);
+            }
+        }
+    }
+
+    fn bypass(&mut self) {
+        self.bypassing = !self.bypassing;
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BiquadType {
+    LowShelf,
+    HighShelf,
+    Peaking,
+}
+
+/// Coefficients of one second order section (RBJ audio EQ cookbook), normalized to a0 = 1
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+/// Delay line of one channel, transposed direct form II
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl Default for Biquad {
+    fn default() -> Self {
+        Biquad::identity()
+    }
+}
+
+impl Biquad {
+    pub fn identity() -> Self {
+        Biquad {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+
+    pub fn new(biquad_type: BiquadType, freq: f32, gain_db: f32, q: f32, sample_rate: f32) -> Self {
+        // keep the center frequency below nyquist and q positive, otherwise the filter gets unstable
+        let freq = freq.clamp(1.0, 0.499 * sample_rate);
+        let q = q.max(0.01);
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match biquad_type {
+            BiquadType::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            BiquadType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+            BiquadType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+        };
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    #[inline]
+    pub fn process(&self, state: &mut BiquadState, x: f32) -> f32 {
+        let y = self.b0 * x + state.z1;
+        state.z1 = self.b1 * x - self.a1 * y + state.z2;
+        state.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+
+    /// magnitude of the transfer function H(e^jw) at freq
+    pub fn magnitude(&self, freq: f32, sample_rate: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        let (cos_w, sin_w) = (w.cos(), w.sin());
+        let (cos_2w, sin_2w) = ((2.0 * w).cos(), (2.0 * w).sin());
+        let num_re = self.b0 + self.b1 * cos_w + self.b2 * cos_2w;
+        let num_im = -self.b1 * sin_w - self.b2 * sin_2w;
+        let den_re = 1.0 + self.a1 * cos_w + self.a2 * cos_2w;
+        let den_im = -self.a1 * sin_w - self.a2 * sin_2w;
+        ((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im)).sqrt()
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::jackmidi::MidiMsgAdvanced;
+
+/// 16 channels of 128 notes in 64 bit words
+const NUM_WORDS: usize = 16 * 128 / 64;
+
+/// Notes bound to a function in the midi mapping, e.g. pads setting a value.
+/// Shared lock free between the midi thread, which keeps it up to date with the mapping,
+/// and the engine in the jack callback, which does not play them.
+#[derive(Clone, Default)]
+pub struct BoundNotes {
+    words: Arc<[AtomicU64; NUM_WORDS]>,
+}
+
+#[inline]
+fn bit_index(channel: u8, note: u8) -> usize {
+    ((channel as usize & 0x0f) << 7) + (note as usize & 0x7f)
+}
+
+impl BoundNotes {
+    pub fn from_midi_mapping<T>(midi_mapping: &HashMap<MidiMsgAdvanced, T>) -> Self {
+        let bound_notes = BoundNotes::default();
+        bound_notes.set_midi_mapping(midi_mapping);
+        bound_notes
+    }
+
+    /// replace the notes with the note bindings of the mapping
+    pub fn set_midi_mapping<T>(&self, midi_mapping: &HashMap<MidiMsgAdvanced, T>) {
+        let mut words = [0u64; NUM_WORDS];
+        for midi_advanced_msg in midi_mapping.keys() {
+            if let MidiMsgAdvanced::MidiNoteOnOff(id_on, _, _, note, _) = *midi_advanced_msg {
+                let index = bit_index((id_on >> 8) as u8, note);
+                words[index / 64] |= 1 << (index % 64);
+            }
+        }
+        for (word, value) in self.words.iter().zip(words) {
+            word.store(value, Ordering::Relaxed);
+        }
+    }
+
+    pub fn contains(&self, channel: u8, note: u8) -> bool {
+        let index = bit_index(channel, note);
+        self.words[index / 64].load(Ordering::Relaxed) & (1 << (index % 64)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_of_the_mapping() {
+        let mut midi_mapping = HashMap::new();
+        // note 36 on channel 10, a controller on channel 1
+        midi_mapping.insert(
+            MidiMsgAdvanced::MidiNoteOnOff(0x9924, 0x8924, true, 36, 0),
+            (),
+        );
+        midi_mapping.insert(MidiMsgAdvanced::MidiControlIdValue(0xB046, 0), ());
+        let bound_notes = BoundNotes::from_midi_mapping(&midi_mapping);
+        assert!(bound_notes.contains(9, 36));
+        assert!(!bound_notes.contains(0, 36));
+        assert!(!bound_notes.contains(9, 37));
+        assert!(!bound_notes.contains(0, 70));
+
+        // the clone in the other thread sees the new mapping
+        let shared = bound_notes.clone();
+        bound_notes.set_midi_mapping(&HashMap::<MidiMsgAdvanced, ()>::new());
+        assert!(!shared.contains(9, 36));
+    }
+}
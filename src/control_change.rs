@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::jackmidi::MidiMsgAdvanced;
+
+const CC_DATA_ENTRY_MSB: u8 = 6;
+const CC_DATA_ENTRY_LSB: u8 = 38;
+const CC_DATA_INCREMENT: u8 = 96;
+const CC_DATA_DECREMENT: u8 = 97;
+const CC_NRPN_LSB: u8 = 98;
+const CC_NRPN_MSB: u8 = 99;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+/// RPN 127/127 deselects the parameter
+const RPN_NULL: u16 = 0x3fff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ParameterNumber {
+    Rpn(u16),
+    Nrpn(u16),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    /// last MSB of controllers 0-31, the LSB is controller + 32
+    msb_values: [Option<u8>; 32],
+    parameter_msb: u8,
+    parameter_lsb: u8,
+    parameter: Option<ParameterNumber>,
+}
+
+/// control id as used in MidiMsgAdvanced
+#[inline]
+pub fn control_id(channel: u8, control: u8) -> u16 {
+    0xB000 + ((channel as u16) << 8) + control as u16
+}
+
+/// Control change handling after the MIDI spec:
+/// controllers 0-31 (MSB) pair with 32-63 (LSB) when switched to 14 bit,
+/// RPN/NRPN parameter numbers select the target of data entry, increment and decrement.
+pub struct ControlChangeDecoder {
+    channels: [ChannelState; 16],
+    /// (channel, msb controller) in 14 bit mode
+    controllers_14bit: HashSet<(u8, u8)>,
+    parameter_values: HashMap<(u8, ParameterNumber), u16>,
+}
+
+impl Default for ControlChangeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ControlChangeDecoder {
+    /// all controllers in 7 bit mode
+    pub fn new() -> Self {
+        ControlChangeDecoder {
+            channels: [ChannelState::default(); 16],
+            controllers_14bit: HashSet::new(),
+            parameter_values: HashMap::new(),
+        }
+    }
+
+    /// a MidiControl2IdsValue with MSB controller n and LSB controller n + 32
+    /// in the mapping switches this controller to 14 bit mode
+    pub fn from_midi_mapping<T>(midi_mapping: &HashMap<MidiMsgAdvanced, T>) -> Self {
+        let mut decoder = ControlChangeDecoder::new();
+        for midi_advanced_msg in midi_mapping.keys() {
+            if let MidiMsgAdvanced::MidiControl2IdsValue(id_msb, id_lsb, _) = *midi_advanced_msg {
+                let (status_msb, channel, control) = split_control_id(id_msb);
+                let (status_lsb, _, control_lsb) = split_control_id(id_lsb);
+                if status_msb == 0xB
+                    && status_lsb == 0xB
+                    && control < 32
+                    && control_lsb == control + 32
+                {
+                    decoder.set_14bit(channel, control, true);
+                }
+            }
+        }
+        decoder
+    }
+
+    pub fn set_14bit(&mut self, channel: u8, control: u8, enable: bool) {
+        if enable {
+            self.controllers_14bit.insert((channel & 0x0f, control));
+        } else {
+            self.controllers_14bit.remove(&(channel & 0x0f, control));
+        }
+    }
+
+    pub fn is_14bit(&self, channel: u8, control: u8) -> bool {
+        self.controllers_14bit.contains(&(channel & 0x0f, control))
+    }
+
+    /// None for messages that only change the decoder state, e.g. a parameter number selection
+    pub fn decode(&mut self, channel: u8, control: u8, value: u8) -> Option<MidiMsgAdvanced> {
+        let channel = channel & 0x0f;
+        match control {
+            CC_NRPN_MSB | CC_NRPN_LSB | CC_RPN_MSB | CC_RPN_LSB => {
+                self.select_parameter(channel, control, value);
+                None
+            }
+            CC_DATA_ENTRY_MSB | CC_DATA_ENTRY_LSB | CC_DATA_INCREMENT | CC_DATA_DECREMENT
+                if self.channels[channel as usize].parameter.is_some() =>
+            {
+                self.parameter_data(channel, control, value)
+            }
+            0..=31 if self.is_14bit(channel, control) => {
+                // a new MSB resets the LSB
+                self.channels[channel as usize].msb_values[control as usize] = Some(value);
+                Some(MidiMsgAdvanced::MidiControl2IdsValue(
+                    control_id(channel, control),
+                    control_id(channel, control + 32),
+                    (value as u16) << 7,
+                ))
+            }
+            32..=63 if self.is_14bit(channel, control - 32) => {
+                let msb = self.channels[channel as usize].msb_values[control as usize - 32]?;
+                Some(MidiMsgAdvanced::MidiControl2IdsValue(
+                    control_id(channel, control - 32),
+                    control_id(channel, control),
+                    ((msb as u16) << 7) + value as u16,
+                ))
+            }
+            _ => Some(MidiMsgAdvanced::MidiControlIdValue(
+                control_id(channel, control),
+                value as u16,
+            )),
+        }
+    }
+
+    fn select_parameter(&mut self, channel: u8, control: u8, value: u8) {
+        let state = &mut self.channels[channel as usize];
+        let is_rpn = match control {
+            CC_NRPN_MSB => {
+                state.parameter_msb = value;
+                false
+            }
+            CC_NRPN_LSB => {
+                state.parameter_lsb = value;
+                false
+            }
+            CC_RPN_MSB => {
+                state.parameter_msb = value;
+                true
+            }
+            _ => {
+                state.parameter_lsb = value;
+                true
+            }
+        };
+        let number = ((state.parameter_msb as u16) << 7) + state.parameter_lsb as u16;
+        state.parameter = match (is_rpn, number) {
+            (true, RPN_NULL) => None,
+            (true, number) => Some(ParameterNumber::Rpn(number)),
+            (false, number) => Some(ParameterNumber::Nrpn(number)),
+        };
+    }
+
+    fn parameter_data(&mut self, channel: u8, control: u8, value: u8) -> Option<MidiMsgAdvanced> {
+        let parameter = self.channels[channel as usize].parameter?;
+        let parameter_value = self
+            .parameter_values
+            .entry((channel, parameter))
+            .or_insert(0);
+        *parameter_value = match control {
+            CC_DATA_ENTRY_MSB => (value as u16) << 7,
+            CC_DATA_ENTRY_LSB => (*parameter_value & 0x3f80) + value as u16,
+            CC_DATA_INCREMENT => (*parameter_value + 1).min(0x3fff),
+            _ => parameter_value.saturating_sub(1),
+        };
+        let id = control_id(channel, 0);
+        Some(match parameter {
+            ParameterNumber::Rpn(number) => {
+                MidiMsgAdvanced::MidiRpnValue(id, number, *parameter_value)
+            }
+            ParameterNumber::Nrpn(number) => {
+                MidiMsgAdvanced::MidiNrpnValue(id, number, *parameter_value)
+            }
+        })
+    }
+}
+
+/// (status, channel, control) of a control id
+#[inline]
+fn split_control_id(id: u16) -> (u8, u8, u8) {
+    (
+        (id >> 12) as u8,
+        ((id >> 8) & 0x0f) as u8,
+        (id & 0xff) as u8,
+    )
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use crate::midi_functions::{
+    parse_json_file_to_midi_functions_with_midi_msgs_advanced, MidiBinding,
+};
+use crate::midi_mapping::parse_midi_mapping;
+
+/// Built-in controller profiles in the midi mapping format, the files in controller_profiles/.
+pub const CONTROLLER_PROFILES: [(&str, &str); 3] = [
+    (
+        "generic_8_knob",
+        include_str!("../controller_profiles/generic_8_knob.json"),
+    ),
+    (
+        "nanokontrol",
+        include_str!("../controller_profiles/nanokontrol.json"),
+    ),
+    (
+        "mpk_mini",
+        include_str!("../controller_profiles/mpk_mini.json"),
+    ),
+];
+
+pub fn get_controller_profile_names() -> Vec<&'static str> {
+    CONTROLLER_PROFILES.iter().map(|(name, _)| *name).collect()
+}
+
+/// built-in profile by name, otherwise a mapping file
+pub fn load_controller_profile(
+    name_or_filepath: &str,
+) -> Result<HashMap<String, Vec<MidiBinding>>, String> {
+    match CONTROLLER_PROFILES
+        .iter()
+        .find(|(name, _)| *name == name_or_filepath)
+    {
+        Some((name, contents)) => parse_midi_mapping(contents)
+            .map_err(|err| format!("controller profile {}: {}", name, err)),
+        None if std::path::Path::new(name_or_filepath).is_file() => {
+            parse_json_file_to_midi_functions_with_midi_msgs_advanced(&name_or_filepath.to_string())
+        }
+        None => Err(format!(
+            "unknown controller profile \"{}\", built-in profiles: {}",
+            name_or_filepath,
+            get_controller_profile_names().join(", ")
+        )),
+    }
+}
+
+/// the bindings of a function in midi_mapping replace the ones of the profile
+pub fn merge_midi_mappings(
+    profile: HashMap<String, Vec<MidiBinding>>,
+    midi_mapping: HashMap<String, Vec<MidiBinding>>,
+) -> HashMap<String, Vec<MidiBinding>> {
+    let mut merged = profile;
+    merged.extend(midi_mapping);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_profiles_parse() {
+        for (name, contents) in CONTROLLER_PROFILES {
+            let midi_functions = parse_midi_mapping(contents)
+                .unwrap_or_else(|err| panic!("controller profile {}: {}", name, err));
+            assert!(!midi_functions.is_empty(), "controller profile {}", name);
+        }
+    }
+
+    #[test]
+    fn load_by_name() {
+        for name in get_controller_profile_names() {
+            assert!(load_controller_profile(name).is_ok(), "{}", name);
+        }
+        assert!(load_controller_profile("no_such_profile").is_err());
+    }
+
+    #[test]
+    fn mapping_replaces_profile_bindings() {
+        let profile = load_controller_profile("generic_8_knob").unwrap();
+        let midi_mapping = load_controller_profile("mpk_mini").unwrap();
+        let merged = merge_midi_mappings(profile.clone(), midi_mapping.clone());
+        assert_eq!(merged["freq_am"], midi_mapping["freq_am"]);
+        assert_eq!(merged["phase_am"], profile["phase_am"]);
+    }
+}
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 
+use crate::stereo::PanMode;
+
 pub type ParameterMap = HashMap<String, Vec<String>>;
 
+#[derive(Clone)]
 pub struct CtrlMsg {
     pub size: usize,
     pub intensity_am: f32,
@@ -10,7 +13,16 @@ pub struct CtrlMsg {
     pub intensity_fm: f32,
     pub freq_fm: f32,
     pub phase_fm: f32,
-    pub num_samples: usize,
     pub volume: f32,
+    /// -1.0..=1.0 per voice with the constant power law, -3 dB in the center leaves headroom at the sides
+    pub pan: f32,
+    pub pan_spread: f32,
+    pub pan_mode: PanMode,
+    pub stereo_width: f32,
+    /// semitones up and down at full pitch bend
+    pub pitch_bend_range: f32,
+    /// vibrato by mod wheel and aftertouch, depth in semitones at full modulation
+    pub vibrato_rate: f32,
+    pub vibrato_depth: f32,
     pub effect_params: Option<ParameterMap>,
 }
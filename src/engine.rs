@@ -0,0 +1,567 @@
+use std::collections::VecDeque;
+
+use crate::adsr::Adsr;
+use crate::bound_notes::BoundNotes;
+use crate::control_change::ControlChangeDecoder;
+use crate::ctrl_msg::CtrlMsg;
+use crate::effect::Effect;
+use crate::jackmidi::{MidiMessage, MidiMsgAdvanced};
+use crate::part::{Part, PartConfig, PartRouting, PITCH_BEND_SMOOTH_FRAMES};
+use crate::trigger_note_msg::TriggerNoteMsg;
+use crate::util::{pitch_bend_from_u14, pitch_bend_range_from_rpn};
+
+/// scheduled events that fit without reallocation in the real-time thread
+const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+#[derive(Clone)]
+pub enum EngineEvent {
+    Trigger(TriggerNoteMsg),
+    Ctrl(CtrlMsg),
+    Adsr(Adsr),
+    /// -1.0..=1.0, scaled by the pitch bend range of the ctrl msg
+    PitchBend(f32),
+    /// semitones, e.g. from RPN 0, until the next ctrl msg
+    PitchBendRange(f32),
+    Sustain(bool),
+    Sostenuto(bool),
+    SoftPedal(bool),
+    /// release all notes, notes kept by a pedal sound until it is released
+    AllNotesOff,
+    /// silence all notes without release
+    AllSoundOff,
+    /// 0.0..=1.0, modulation sources for the vibrato
+    ModWheel(f32),
+    Aftertouch(f32),
+    /// midi channel, level and output routing of a part
+    PartConfig(PartConfig),
+}
+
+/// notes, pitch bend, pedals and modulation from raw midi,
+/// other messages are not handled by the engine
+pub fn engine_event_from_midi(bytes: &[u8], note_length: usize) -> Option<EngineEvent> {
+    match MidiMessage::from_bytes(bytes) {
+        MidiMessage::PitchBend { value, .. } => {
+            Some(EngineEvent::PitchBend(pitch_bend_from_u14(value)))
+        }
+        MidiMessage::ControlChange { control, value, .. } => match control {
+            1 => Some(EngineEvent::ModWheel(value as f32 / 127.0)),
+            64 => Some(EngineEvent::Sustain(value >= 64)),
+            66 => Some(EngineEvent::Sostenuto(value >= 64)),
+            67 => Some(EngineEvent::SoftPedal(value >= 64)),
+            120 => Some(EngineEvent::AllSoundOff),
+            // omni and mono/poly mode changes imply all notes off
+            123..=127 => Some(EngineEvent::AllNotesOff),
+            _ => None,
+        },
+        MidiMessage::ChannelAftertouch { pressure, .. }
+        | MidiMessage::PolyAftertouch { pressure, .. } => {
+            Some(EngineEvent::Aftertouch(pressure as f32 / 127.0))
+        }
+        _ => TriggerNoteMsg::from_midi_bytes(bytes, note_length).map(EngineEvent::Trigger),
+    }
+}
+
+/// like engine_event_from_midi, control changes also go through the decoder
+/// so that RPN 0 sets the pitch bend range
+pub fn decode_engine_event(
+    bytes: &[u8],
+    note_length: usize,
+    control_change_decoder: &mut ControlChangeDecoder,
+) -> Option<EngineEvent> {
+    if let MidiMessage::ControlChange {
+        channel,
+        control,
+        value,
+    } = MidiMessage::from_bytes(bytes)
+    {
+        if let Some(MidiMsgAdvanced::MidiRpnValue(_, 0, value)) =
+            control_change_decoder.decode(channel, control, value)
+        {
+            return Some(EngineEvent::PitchBendRange(pitch_bend_range_from_rpn(
+                value,
+            )));
+        }
+    }
+    engine_event_from_midi(bytes, note_length)
+}
+
+/// the parts an event is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTarget {
+    AllParts,
+    /// parts listening to this midi channel, 0..=15
+    Channel(u8),
+    Part(usize),
+}
+
+impl EventTarget {
+    /// channel messages go to the parts of their channel, system messages to all parts
+    pub fn from_midi_bytes(bytes: &[u8]) -> Self {
+        match MidiMessage::from_bytes(bytes).get_channel() {
+            Some(channel) => Self::Channel(channel),
+            None => Self::AllParts,
+        }
+    }
+}
+
+pub struct ScheduledEvent {
+    pub frame: u64,
+    pub target: EventTarget,
+    pub event: EngineEvent,
+}
+
+/// The synth core without any audio backend: parts with their voices, effect chains and control state.
+/// Driven either period by period from the jack callback or offline with scheduled events.
+pub struct Engine {
+    parts: Vec<Part>,
+    sample_rate: f32,
+    max_frame_size: usize,
+    frame_pos: u64,
+    events: VecDeque<ScheduledEvent>,
+    part_out_l: Vec<f32>,
+    part_out_r: Vec<f32>,
+    /// notes bound to a function are not played from midi
+    bound_notes: BoundNotes,
+    /// parameter numbers selected on each channel
+    control_change_decoder: ControlChangeDecoder,
+}
+
+impl Engine {
+    /// engine with one omni part and the default effect chain: overdrive, equalizer, autopan
+    pub fn new(sample_rate: f32, max_frame_size: usize) -> Self {
+        Engine::with_parts(
+            sample_rate,
+            max_frame_size,
+            vec![Part::new(sample_rate, max_frame_size)],
+        )
+    }
+
+    /// engine with one omni part
+    pub fn with_effect_chain(
+        sample_rate: f32,
+        max_frame_size: usize,
+        effect_chain: Vec<Box<dyn Effect>>,
+    ) -> Self {
+        Engine::with_parts(
+            sample_rate,
+            max_frame_size,
+            vec![Part::with_effect_chain(
+                sample_rate,
+                max_frame_size,
+                effect_chain,
+            )],
+        )
+    }
+
+    /// parts must be created with the same sample rate and max frame size
+    pub fn with_parts(sample_rate: f32, max_frame_size: usize, parts: Vec<Part>) -> Self {
+        Engine {
+            parts,
+            sample_rate,
+            max_frame_size,
+            frame_pos: 0,
+            events: VecDeque::with_capacity(EVENT_QUEUE_CAPACITY),
+            part_out_l: vec![0.0; max_frame_size],
+            part_out_r: vec![0.0; max_frame_size],
+            bound_notes: BoundNotes::default(),
+            control_change_decoder: ControlChangeDecoder::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// number of frames rendered so far
+    pub fn frame_pos(&self) -> u64 {
+        self.frame_pos
+    }
+
+    /// add a part with the default effect chain, returns its index
+    pub fn add_part(&mut self, config: PartConfig) -> usize {
+        let mut part = Part::new(self.sample_rate, self.max_frame_size);
+        part.set_config(config);
+        self.parts.push(part);
+        self.parts.len() - 1
+    }
+
+    pub fn set_bound_notes(&mut self, bound_notes: BoundNotes) {
+        self.bound_notes = bound_notes;
+    }
+
+    pub fn num_parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn get_part(&self, index: usize) -> Option<&Part> {
+        self.parts.get(index)
+    }
+
+    pub fn get_part_mut(&mut self, index: usize) -> Option<&mut Part> {
+        self.parts.get_mut(index)
+    }
+
+    /// apply an event to all parts right now, before the next rendered frame
+    pub fn handle_event(&mut self, event: EngineEvent) {
+        self.handle_event_to(EventTarget::AllParts, event);
+    }
+
+    /// apply an event right now to the parts of the target, unknown parts are ignored
+    pub fn handle_event_to(&mut self, target: EventTarget, event: EngineEvent) {
+        match target {
+            EventTarget::Part(index) => {
+                if let Some(part) = self.parts.get_mut(index) {
+                    part.handle_event(event);
+                }
+            }
+            EventTarget::AllParts | EventTarget::Channel(_) => {
+                let mut event = Some(event);
+                let num_parts = self.parts.len();
+                for (index, part) in self.parts.iter_mut().enumerate() {
+                    if let EventTarget::Channel(channel) = target {
+                        if !part.get_config().channel.accepts(channel) {
+                            continue;
+                        }
+                    }
+                    // the last part gets the original, no clone of the ctrl msg maps
+                    let part_event = if index + 1 == num_parts {
+                        event.take()
+                    } else {
+                        event.clone()
+                    };
+                    if let Some(part_event) = part_event {
+                        part.handle_event(part_event);
+                    }
+                }
+            }
+        }
+    }
+
+    /// queue an event for all parts at an absolute frame position, events at the same frame keep their order
+    pub fn schedule(&mut self, frame: u64, event: EngineEvent) {
+        self.schedule_to(frame, EventTarget::AllParts, event);
+    }
+
+    /// queue an event for the parts of the target at an absolute frame position
+    pub fn schedule_to(&mut self, frame: u64, target: EventTarget, event: EngineEvent) {
+        let index = self
+            .events
+            .partition_point(|scheduled| scheduled.frame <= frame);
+        self.events.insert(
+            index,
+            ScheduledEvent {
+                frame,
+                target,
+                event,
+            },
+        );
+    }
+
+    /// queue notes, pitch bend and its range, pedals and modulation from raw midi at a frame offset
+    /// into the next processed period, only parts listening to the channel of the message get it,
+    /// note ons of bound notes are skipped
+    pub fn schedule_midi(&mut self, frame_offset: u32, bytes: &[u8], length: usize) {
+        if let MidiMessage::NoteOn {
+            channel,
+            key,
+            velocity: 1..,
+        } = MidiMessage::from_bytes(bytes)
+        {
+            if self.bound_notes.contains(channel, key) {
+                return;
+            }
+        }
+        if let Some(event) = decode_engine_event(bytes, length, &mut self.control_change_decoder) {
+            self.schedule_to(
+                self.frame_pos + frame_offset as u64,
+                EventTarget::from_midi_bytes(bytes),
+                event,
+            );
+        }
+    }
+
+    pub fn schedule_events(&mut self, events: impl IntoIterator<Item = ScheduledEvent>) {
+        for scheduled in events {
+            self.schedule_to(scheduled.frame, scheduled.target, scheduled.event);
+        }
+    }
+
+    pub fn pending_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// render output_l.len() frames of all parts mixed, scheduled events are applied at their exact frame
+    pub fn process(&mut self, output_l: &mut [f32], output_r: &mut [f32]) {
+        self.process_routed(output_l, output_r, &mut []);
+    }
+
+    /// like process, parts routed to Separate are written to part_outputs at their index instead of the mix,
+    /// parts without an entry in part_outputs stay in the mix
+    pub fn process_routed(
+        &mut self,
+        output_l: &mut [f32],
+        output_r: &mut [f32],
+        part_outputs: &mut [(&mut [f32], &mut [f32])],
+    ) {
+        let num_frames = output_l.len().min(output_r.len());
+        output_l.fill(0.0);
+        output_r.fill(0.0);
+        for (part_output_l, part_output_r) in part_outputs.iter_mut() {
+            part_output_l.fill(0.0);
+            part_output_r.fill(0.0);
+        }
+        let mut start = 0;
+        while start < num_frames {
+            while let Some(scheduled) = self.events.front() {
+                if scheduled.frame > self.frame_pos {
+                    break;
+                }
+                if let Some(scheduled) = self.events.pop_front() {
+                    self.handle_event_to(scheduled.target, scheduled.event);
+                }
+            }
+            let mut block_size = (num_frames - start).min(self.max_frame_size);
+            if let Some(scheduled) = self.events.front() {
+                block_size = block_size.min((scheduled.frame - self.frame_pos) as usize);
+            }
+            if self.parts.iter().any(|part| part.is_modulating()) {
+                block_size = block_size.min(PITCH_BEND_SMOOTH_FRAMES);
+            }
+            let end = start + block_size;
+            let part_out_l = &mut self.part_out_l[..block_size];
+            let part_out_r = &mut self.part_out_r[..block_size];
+            for (index, part) in self.parts.iter_mut().enumerate() {
+                part.process_block(part_out_l, part_out_r);
+                let config = part.get_config();
+                let (target_l, target_r) = match (config.routing, part_outputs.get_mut(index)) {
+                    (PartRouting::Separate, Some((part_output_l, part_output_r))) => (
+                        &mut part_output_l[start..end],
+                        &mut part_output_r[start..end],
+                    ),
+                    _ => (&mut output_l[start..end], &mut output_r[start..end]),
+                };
+                mix_into(target_l, part_out_l, config.level);
+                mix_into(target_r, part_out_r, config.level);
+            }
+            self.frame_pos += block_size as u64;
+            start = end;
+        }
+    }
+
+    /// offline rendering into newly allocated buffers
+    pub fn render(&mut self, frames: usize) -> (Vec<f32>, Vec<f32>) {
+        let mut output_l = vec![0.0; frames];
+        let mut output_r = vec![0.0; frames];
+        self.process(&mut output_l, &mut output_r);
+        (output_l, output_r)
+    }
+}
+
+#[inline]
+fn mix_into(output: &mut [f32], input: &[f32], level: f32) {
+    for (out, sample) in output.iter_mut().zip(input) {
+        *out += sample * level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::part::MidiChannel;
+    use crate::trigger_note_msg::NoteType;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const MAX_FRAME_SIZE: usize = 64;
+
+    fn note(note_type: NoteType, freq: f32) -> EngineEvent {
+        EngineEvent::Trigger(TriggerNoteMsg {
+            note_type,
+            freq,
+            velocity: 1.0,
+            length: 48000,
+        })
+    }
+
+    /// engine without effects, so the output follows the voices directly
+    fn dry_engine() -> Engine {
+        Engine::with_effect_chain(SAMPLE_RATE, MAX_FRAME_SIZE, Vec::new())
+    }
+
+    fn part_on_channel(channel: MidiChannel) -> Part {
+        let mut part = Part::with_effect_chain(SAMPLE_RATE, MAX_FRAME_SIZE, Vec::new());
+        part.set_config(PartConfig {
+            channel,
+            ..PartConfig::default()
+        });
+        part
+    }
+
+    #[test]
+    fn event_at_exact_frame_across_blocks() {
+        let mut engine = dry_engine();
+        engine.schedule(0, EngineEvent::Adsr(Adsr::new(0.01, 0.01, 1.0, 0.01)));
+        // neither frame is on a block boundary of MAX_FRAME_SIZE
+        engine.schedule(0, note(NoteType::NoteOn, 1000.0));
+        engine.schedule(100, EngineEvent::AllSoundOff);
+        engine.schedule(201, note(NoteType::NoteOn, 1000.0));
+        let (output_l, output_r) = engine.render(400);
+        assert_eq!(engine.frame_pos(), 400);
+        assert_eq!(engine.pending_events(), 0);
+        assert!(output_l[90..100].iter().any(|sample| *sample != 0.0));
+        assert!(output_l[100..201].iter().all(|sample| *sample == 0.0));
+        assert!(output_r[100..201].iter().all(|sample| *sample == 0.0));
+        assert!(output_l[201..210].iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn event_at_exact_frame_across_process_calls() {
+        let mut engine = dry_engine();
+        engine.schedule(0, note(NoteType::NoteOn, 1000.0));
+        engine.schedule(150, EngineEvent::AllSoundOff);
+        let mut output_l = vec![0.0; 100];
+        let mut output_r = vec![0.0; 100];
+        engine.process(&mut output_l, &mut output_r);
+        assert!(output_l[90..].iter().any(|sample| *sample != 0.0));
+        engine.process(&mut output_l, &mut output_r);
+        assert!(output_l[40..50].iter().any(|sample| *sample != 0.0));
+        assert!(output_l[50..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn same_frame_keeps_insertion_order() {
+        let mut engine = dry_engine();
+        engine.schedule(20, EngineEvent::PitchBend(1.0));
+        engine.schedule(10, EngineEvent::PitchBend(0.5));
+        engine.schedule(10, EngineEvent::PitchBend(-0.25));
+        engine.schedule(10, EngineEvent::Sustain(true));
+        engine.schedule(10, EngineEvent::Sustain(false));
+        engine.render(15);
+        let part = engine.get_part(0).unwrap();
+        assert_eq!(part.get_pitch_bend(), -0.25);
+        assert!(!part.get_pedal_state().get_sustain());
+        engine.render(10);
+        assert_eq!(engine.get_part(0).unwrap().get_pitch_bend(), 1.0);
+    }
+
+    #[test]
+    fn render_is_deterministic() {
+        let render = || {
+            let mut engine = Engine::new(SAMPLE_RATE, MAX_FRAME_SIZE);
+            engine.schedule(0, note(NoteType::NoteOn, 440.0));
+            engine.schedule(333, note(NoteType::NoteOn, 660.0));
+            engine.schedule(1000, EngineEvent::PitchBend(0.5));
+            engine.schedule(1500, EngineEvent::ModWheel(1.0));
+            engine.schedule(3000, note(NoteType::NoteOff, 440.0));
+            engine.render(4800)
+        };
+        let (first_l, first_r) = render();
+        let (second_l, second_r) = render();
+        let to_bits = |samples: &[f32]| samples.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(to_bits(&first_l), to_bits(&second_l));
+        assert_eq!(to_bits(&first_r), to_bits(&second_r));
+        assert!(first_l.iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn channel_target_reaches_accepting_parts() {
+        let parts = vec![
+            part_on_channel(MidiChannel::Channel(0)),
+            part_on_channel(MidiChannel::Channel(1)),
+            part_on_channel(MidiChannel::Omni),
+            part_on_channel(MidiChannel::Off),
+        ];
+        let mut engine = Engine::with_parts(SAMPLE_RATE, MAX_FRAME_SIZE, parts);
+        engine.schedule_to(0, EventTarget::Channel(1), EngineEvent::Sustain(true));
+        // sostenuto on channel 0, shown as 1
+        engine.schedule_midi(0, &[0xB0, 66, 127], 0);
+        engine.render(1);
+        let pedals = |engine: &Engine| -> Vec<(bool, bool)> {
+            (0..engine.num_parts())
+                .map(|index| {
+                    let pedal_state = engine.get_part(index).unwrap().get_pedal_state();
+                    (pedal_state.get_sustain(), pedal_state.get_sostenuto())
+                })
+                .collect()
+        };
+        assert_eq!(
+            pedals(&engine),
+            vec![(false, true), (true, false), (true, true), (false, false)]
+        );
+        engine.schedule_to(1, EventTarget::Part(3), EngineEvent::Sustain(true));
+        engine.schedule_to(1, EventTarget::Part(7), EngineEvent::Sustain(true));
+        engine.render(1);
+        assert!(engine.get_part(3).unwrap().get_pedal_state().get_sustain());
+    }
+
+    #[test]
+    fn bound_note_is_not_played() {
+        let mut midi_mapping = std::collections::HashMap::new();
+        // pad on note 36 of channel 10
+        midi_mapping.insert(
+            MidiMsgAdvanced::MidiNoteOnOff(0x9924, 0x8924, true, 36, 0),
+            (),
+        );
+        let mut engine = dry_engine();
+        engine.set_bound_notes(BoundNotes::from_midi_mapping(&midi_mapping));
+        engine.schedule_midi(0, &[0x99, 36, 100], 0);
+        assert_eq!(engine.pending_events(), 0);
+        // the same note on another channel and other notes play
+        engine.schedule_midi(0, &[0x90, 36, 100], 0);
+        engine.schedule_midi(0, &[0x99, 37, 100], 0);
+        // note offs pass, the note may have started before it was bound
+        engine.schedule_midi(0, &[0x89, 36, 0], 0);
+        let triggers: Vec<(NoteType, EventTarget)> = engine
+            .events
+            .iter()
+            .filter_map(|scheduled| match scheduled.event {
+                EngineEvent::Trigger(ref trigger) => Some((trigger.note_type, scheduled.target)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            triggers,
+            vec![
+                (NoteType::NoteOn, EventTarget::Channel(0)),
+                (NoteType::NoteOn, EventTarget::Channel(9)),
+                (NoteType::NoteOff, EventTarget::Channel(9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rpn_0_sets_the_pitch_bend_range_of_the_channel() {
+        let parts = vec![
+            part_on_channel(MidiChannel::Channel(0)),
+            part_on_channel(MidiChannel::Channel(1)),
+        ];
+        let mut engine = Engine::with_parts(SAMPLE_RATE, MAX_FRAME_SIZE, parts);
+        // 12 semitones and 50 cents on channel 2
+        for bytes in [
+            [0xB1, 101, 0],
+            [0xB1, 100, 0],
+            [0xB1, 6, 12],
+            [0xB1, 38, 50],
+        ] {
+            engine.schedule_midi(0, &bytes, 0);
+        }
+        engine.render(1);
+        let ranges = |engine: &Engine| -> Vec<f32> {
+            (0..engine.num_parts())
+                .map(|index| engine.get_part(index).unwrap().get_pitch_bend_range())
+                .collect()
+        };
+        assert_eq!(ranges(&engine), vec![2.0, 12.5]);
+        // data entry of another parameter keeps the range
+        for bytes in [
+            [0xB1, 100, 1],
+            [0xB1, 6, 64],
+            [0xB1, 101, 127],
+            [0xB1, 100, 127],
+        ] {
+            engine.schedule_midi(0, &bytes, 0);
+        }
+        engine.schedule_midi(0, &[0xB1, 6, 3], 0);
+        engine.render(1);
+        assert_eq!(ranges(&engine), vec![2.0, 12.5]);
+    }
+}
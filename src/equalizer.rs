@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::biquad::{Biquad, BiquadState, BiquadType};
+use crate::effect::Effect;
+
+pub type ParameterMap = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    pub band_type: BiquadType,
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    pub fn new(band_type: BiquadType, freq: f32, gain_db: f32, q: f32) -> Self {
+        EqBand {
+            band_type,
+            freq,
+            gain_db,
+            q,
+        }
+    }
+
+    /// "band <index> <freq> <gain_db> <q>" as used in the effect parameter map
+    pub fn to_param(&self, index: usize) -> String {
+        format!("band {} {} {} {}", index, self.freq, self.gain_db, self.q)
+    }
+}
+
+pub fn default_eq_bands() -> Vec<EqBand> {
+    vec![
+        EqBand::new(BiquadType::LowShelf, 100.0, 0.0, 0.707),
+        EqBand::new(BiquadType::Peaking, 400.0, 0.0, 1.0),
+        EqBand::new(BiquadType::Peaking, 1500.0, 0.0, 1.0),
+        EqBand::new(BiquadType::Peaking, 4000.0, 0.0, 1.0),
+        EqBand::new(BiquadType::HighShelf, 8000.0, 0.0, 0.707),
+    ]
+}
+
+pub struct Equalizer {
+    pub bypassing: bool,
+    bands: Vec<EqBand>,
+    sections: Vec<Biquad>,
+    states_l: Vec<BiquadState>,
+    states_r: Vec<BiquadState>,
+    sample_rate: f32,
+}
+
+impl Equalizer {
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for index in 0..self.bands.len() {
+            self.update_section(index);
+        }
+    }
+
+    pub fn set_band(&mut self, index: usize, band: EqBand) {
+        if index < self.bands.len() && self.bands[index] != band {
+            self.bands[index] = band;
+            self.update_section(index);
+        }
+    }
+
+    pub fn get_bands(&self) -> &[EqBand] {
+        &self.bands
+    }
+
+    /// combined gain of all bands in dB at freq
+    pub fn magnitude_db(&self, freq: f32) -> f32 {
+        let magnitude: f32 = self
+            .sections
+            .iter()
+            .map(|section| section.magnitude(freq, self.sample_rate))
+            .product();
+        20.0 * magnitude.max(1e-6).log10()
+    }
+
+    fn update_section(&mut self, index: usize) {
+        let band = self.bands[index];
+        self.sections[index] = Biquad::new(
+            band.band_type,
+            band.freq,
+            band.gain_db,
+            band.q,
+            self.sample_rate,
+        );
+    }
+}
+
+impl Effect for Equalizer {
+    fn new() -> Self {
+        let bands = default_eq_bands();
+        let num_bands = bands.len();
+        let mut equalizer = Equalizer {
+            bypassing: false,
+            bands,
+            sections: vec![Biquad::identity(); num_bands],
+            states_l: vec![BiquadState::default(); num_bands],
+            states_r: vec![BiquadState::default(); num_bands],
+            sample_rate: 48000.0,
+        };
+        equalizer.set_sample_rate(48000.0);
+        equalizer
+    }
+
+    fn name(&self) -> &'static str {
+        "equalizer"
+    }
+
+    fn set_params(&mut self, params: &ParameterMap) {
+        if let Some(params) = params.get("equalizer") {
+            for param in params {
+                let param_parts: Vec<&str> = param.split(' ').collect();
+                match param_parts[..] {
+                    ["band", index, freq, gain_db, q] => {
+                        if let (Ok(index), Ok(freq), Ok(gain_db), Ok(q)) = (
+                            index.parse::<usize>(),
+                            freq.parse::<f32>(),
+                            gain_db.parse::<f32>(),
+                            q.parse::<f32>(),
+                        ) {
+                            if let Some(band) = self.bands.get(index) {
+                                let band_type = band.band_type;
+                                self.set_band(index, EqBand::new(band_type, freq, gain_db, q));
+                            }
+                        }
+                    }
+                    ["bypass", bypass] => {
+                        if let Ok(bypass) = bypass.parse() {
+                            self.bypassing = bypass;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn process_samples(
+        &mut self,
+        input_l: Option<&[f32]>,
+        input_r: Option<&[f32]>,
+        output_l: Option<&mut [f32]>,
+        output_r: Option<&mut [f32]>,
+    ) {
+        if self.bypassing {
+            if let Some(input_l) = input_l {
+                if let Some(output_l) = output_l {
+                    output_l.clone_from_slice(input_l);
+                }
+            }
+            if let Some(input_r) = input_r {
+                if let Some(output_r) = output_r {
+                    output_r.clone_from_slice(input_r);
+                }
+            }
+            return;
+        }
+
+        let sections = &self.sections;
+        let process_eq_on_slice =
+            |input: Option<&[f32]>, output: Option<&mut [f32]>, states: &mut [BiquadState]| {
+                if let Some(input) = input {
+                    if let Some(output) = output {
+                        for (index, x) in input.iter().enumerate() {
+                            let mut y = *x;
+                            for (section, state) in sections.iter().zip(states.iter_mut()) {
+                                y = section.process(state, y);
+                            }
+                            output[index] = y;
+                        }
+                    }
+                }
+            };
+
+        process_eq_on_slice(input_l, output_l, &mut self.states_l);
+        process_eq_on_slice(input_r, output_r, &mut self.states_r);
+    }
+
+    fn bypass(&mut self) {
+        self.bypassing = !self.bypassing;
+    }
+}
@@ -0,0 +1,64 @@
+use bus::BusReader;
+use crossbeam_channel::Sender;
+use std::time::{Duration, SystemTime};
+
+/// Detects changed files by their modification time, without platform specific notifications.
+pub struct FileWatcher {
+    files: Vec<(String, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new(filepaths: Vec<String>) -> Self {
+        let files = filepaths
+            .into_iter()
+            .map(|filepath| {
+                let modified = get_modified(&filepath);
+                (filepath, modified)
+            })
+            .collect();
+        FileWatcher { files }
+    }
+
+    /// files modified, created or removed since the last poll
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut changed = Vec::new();
+        for (filepath, modified) in self.files.iter_mut() {
+            let new_modified = get_modified(filepath);
+            if new_modified != *modified {
+                *modified = new_modified;
+                changed.push(filepath.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn get_modified(filepath: &str) -> Option<SystemTime> {
+    std::fs::metadata(filepath)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// sends the path of each changed file until the close bus says stop
+pub fn watch_files(
+    filepaths: Vec<String>,
+    interval: Duration,
+    tx_changed: Sender<String>,
+    mut rx_close: BusReader<bool>,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut file_watcher = FileWatcher::new(filepaths);
+        loop {
+            std::thread::sleep(interval);
+            if let Ok(false) = rx_close.try_recv() {
+                break;
+            }
+            for filepath in file_watcher.poll() {
+                if tx_changed.send(filepath).is_err() {
+                    return;
+                }
+            }
+        }
+        println!("exit file watcher thread\n");
+    })
+}
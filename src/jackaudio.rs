@@ -15,6 +15,20 @@ pub struct SineWaveGenerator {
     pub offset: usize,
     pub frame_size: usize,
     pub fs: f32,
+    pub phase_base: f64,
+}
+
+impl SineWaveGenerator {
+    /// change the frequency without a jump in the phase of the carrier
+    pub fn set_freq(&mut self, freq: f32) {
+        if freq != self.freq {
+            let delta = 2.0 * std::f64::consts::PI * (self.freq as f64 - freq as f64)
+                / self.fs as f64
+                * self.offset as f64;
+            self.phase_base = (self.phase_base + delta).rem_euclid(2.0 * std::f64::consts::PI);
+            self.freq = freq;
+        }
+    }
 }
 
 impl Wave for SineWaveGenerator {
@@ -31,11 +45,12 @@ impl Wave for SineWaveGenerator {
             offset: 0,
             frame_size,
             fs: sample_rate,
+            phase_base: 0.0,
         }
     }
 
     fn process_samples(&mut self, output_l: &mut [f32], output_r: &mut [f32]) {
-        let my_sine = SineWave::new(
+        let mut my_sine = SineWave::new(
             self.freq as f64,
             self.amplitude as f64,
             self.intensity_am as f64,
@@ -48,6 +63,7 @@ impl Wave for SineWaveGenerator {
             self.frame_size,
             self.offset,
         );
+        my_sine.set_phase_base(self.phase_base);
         let (_, values_data) = my_sine.gen_values();
 
         for i in 0..self.frame_size {
@@ -58,7 +74,7 @@ impl Wave for SineWaveGenerator {
     }
 
     fn ctrl(&mut self, msg: &CtrlMsg, freq: f32) {
-        self.freq = freq;
+        self.set_freq(freq);
         self.amplitude = msg.volume;
         self.intensity_am = msg.intensity_am;
         self.freq_am = msg.freq_am;
@@ -1,8 +1,11 @@
+use crate::control_change::ControlChangeDecoder;
 use crate::util::*;
 use serde::{Deserialize, Serialize};
 use std::convert::From;
 
 const MAX_MIDI: usize = 3;
+/// longest message MidiMsgGeneric can hold, longer sysex messages are cut off
+pub const MAX_MIDI_GENERIC: usize = 32;
 type MidiId = u16;
 type Note = u8;
 type Intensity = u8;
@@ -13,6 +16,9 @@ pub enum MidiMsgAdvanced {
     MidiNoteOnOff(MidiId, MidiId, bool, Note, Intensity),
     MidiControlIdValue(MidiId, u16),
     MidiControl2IdsValue(MidiId, MidiId, u16),
+    /// channel id, parameter number, 14 bit value
+    MidiRpnValue(MidiId, u16, u16),
+    MidiNrpnValue(MidiId, u16, u16),
 }
 
 impl MidiMsgAdvanced {
@@ -22,6 +28,8 @@ impl MidiMsgAdvanced {
             Self::MidiNoteOnOff(id0, _, _, _, _) => *id0,
             Self::MidiControlIdValue(id, _) => *id,
             Self::MidiControl2IdsValue(id0, _, _) => *id0,
+            Self::MidiRpnValue(id, _, _) => *id,
+            Self::MidiNrpnValue(id, _, _) => *id,
         }
     }
 
@@ -31,6 +39,8 @@ impl MidiMsgAdvanced {
             Self::MidiNoteOnOff(_, _, _, _, value) => *value as u16,
             Self::MidiControlIdValue(_, value) => *value,
             Self::MidiControl2IdsValue(_, _, value) => *value,
+            Self::MidiRpnValue(_, _, value) => *value,
+            Self::MidiNrpnValue(_, _, value) => *value,
         }
     }
 
@@ -40,6 +50,8 @@ impl MidiMsgAdvanced {
             Self::MidiNoteOnOff(_, _, _, _, value) => *value as f32 / 127.0,
             Self::MidiControlIdValue(_, value) => *value as f32 / 127.0,
             Self::MidiControl2IdsValue(_, _, value) => *value as f32 / 16383.0,
+            Self::MidiRpnValue(_, _, value) => *value as f32 / 16383.0,
+            Self::MidiNrpnValue(_, _, value) => *value as f32 / 16383.0,
         }
     }
 
@@ -53,6 +65,8 @@ impl MidiMsgAdvanced {
             Self::MidiControl2IdsValue(id0, id1, _) => {
                 *self = Self::MidiControl2IdsValue(*id0, *id1, 0)
             }
+            Self::MidiRpnValue(id, number, _) => *self = Self::MidiRpnValue(*id, *number, 0),
+            Self::MidiNrpnValue(id, number, _) => *self = Self::MidiNrpnValue(*id, *number, 0),
         }
     }
 }
@@ -108,62 +122,35 @@ impl MidiMsgAdvanced {
         )
     }
 
-    pub fn from_current_and_last_opt_midi_msgs(
-        (current_midi_msg, last_opt_midi_msg): (
-            Box<dyn MidiMsgBase>,
-            &mut Option<Box<dyn MidiMsgBase>>,
-        ),
+    /// control changes go through the decoder for 14 bit controllers and RPN/NRPN
+    pub fn from_midi_message(
+        midi_message: MidiMessage,
+        control_change_decoder: &mut ControlChangeDecoder,
     ) -> Option<Self> {
-        let mut id = current_midi_msg.get_id();
-        let last_last_midi_msg = last_opt_midi_msg.take();
-        let midi_msg_value = current_midi_msg.get_value();
-        let midi_msgs_data = current_midi_msg.get_data();
-        let midi_msg_type = current_midi_msg.type_of().to_string();
-        let midi_msg_timestamp = current_midi_msg.get_time();
-        let mut id_value_time_diff_to_last_msg = None;
-        if let Some(last_last_midi_msg) = last_last_midi_msg {
-            let time_diff = midi_msg_timestamp.abs_diff(last_last_midi_msg.get_time());
-            let last_id = last_last_midi_msg.get_id();
-            let last_value = last_last_midi_msg.get_value();
-            if time_diff < 10 && id > last_id {
-                id_value_time_diff_to_last_msg = Some((last_id, last_value, time_diff));
-            }
-        }
-        *last_opt_midi_msg = Some(current_midi_msg);
-        match midi_msg_type.as_str() {
-            "MidiMsgControlChange" => {
-                if let Some((last_id, last_value, _time_diff)) = id_value_time_diff_to_last_msg {
-                    Some(MidiMsgAdvanced::MidiControl2IdsValue(
-                        last_id,
-                        id,
-                        midi_msg_value + last_value * 128,
-                    ))
-                } else {
-                    Some(MidiMsgAdvanced::MidiControlIdValue(id, midi_msg_value))
-                }
-            }
-            "MidiMsgNoteOn" => Some(MidiMsgAdvanced::MidiNoteOnOff(
+        let id = midi_message.get_id();
+        match midi_message {
+            MidiMessage::ControlChange {
+                channel,
+                control,
+                value,
+            } => control_change_decoder.decode(channel, control, value),
+            MidiMessage::NoteOn { key, velocity, .. } => Some(MidiMsgAdvanced::MidiNoteOnOff(
                 id,
                 id - 0x1000,
                 true,
-                midi_msgs_data[1],
-                midi_msgs_data[2],
+                key,
+                velocity,
             )),
-            "MidiMsgNoteOff" => {
-                id += 0x1000;
-                Some(MidiMsgAdvanced::MidiNoteOnOff(
-                    id,
-                    id - 0x1000,
-                    false,
-                    midi_msgs_data[1],
-                    midi_msgs_data[2],
-                ))
-            }
-            "MidiMsgPitchBend" => Some(MidiMsgAdvanced::MidiControl2IdsValue(
+            MidiMessage::NoteOff { key, velocity, .. } => Some(MidiMsgAdvanced::MidiNoteOnOff(
+                id + 0x1000,
                 id,
-                id,
-                midi_msg_value,
+                false,
+                key,
+                velocity,
             )),
+            MidiMessage::PitchBend { value, .. } => {
+                Some(MidiMsgAdvanced::MidiControl2IdsValue(id, id, value))
+            }
             _ => None,
         }
     }
@@ -186,6 +173,12 @@ impl std::fmt::Display for MidiMsgAdvanced {
             Self::MidiControl2IdsValue(id0, id1, value) => {
                 write!(f, "MidiControl2IdsValue({}, {}, {})", id0, id1, value)
             }
+            Self::MidiRpnValue(id, number, value) => {
+                write!(f, "MidiRpnValue({}, {}, {})", id, number, value)
+            }
+            Self::MidiNrpnValue(id, number, value) => {
+                write!(f, "MidiNrpnValue({}, {}, {})", id, number, value)
+            }
         }
     }
 }
@@ -207,6 +200,12 @@ impl std::fmt::Debug for MidiMsgAdvanced {
             Self::MidiControl2IdsValue(id0, id1, value) => {
                 write!(f, "MidiControl2IdsValue({}, {}, {})", id0, id1, value)
             }
+            Self::MidiRpnValue(id, number, value) => {
+                write!(f, "MidiRpnValue({}, {}, {})", id, number, value)
+            }
+            Self::MidiNrpnValue(id, number, value) => {
+                write!(f, "MidiNrpnValue({}, {}, {})", id, number, value)
+            }
         }
     }
 }
@@ -256,16 +255,29 @@ pub trait MidiMsgBase: Send + std::fmt::Display {
 #[derive(Copy, Clone)]
 pub struct MidiMsgGeneric {
     pub len: usize,
-    pub data: [u8; MAX_MIDI],
+    pub data: [u8; MAX_MIDI_GENERIC],
     pub time: u64,
 }
 
+impl MidiMsgGeneric {
+    pub fn from_bytes(bytes: &[u8], time: u64) -> Self {
+        let len = std::cmp::min(MAX_MIDI_GENERIC, bytes.len());
+        let mut data = [0; MAX_MIDI_GENERIC];
+        data[..len].copy_from_slice(&bytes[..len]);
+        MidiMsgGeneric { len, data, time }
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
 impl MidiMsgBase for MidiMsgGeneric {
     fn type_of(&self) -> &str {
         "MidiMsgGeneric"
     }
     fn get_data(&self) -> Vec<u8> {
-        self.data.into_iter().collect()
+        self.get_bytes().to_vec()
     }
     fn get_id(&self) -> u16 {
         u16::max_value()
@@ -305,13 +317,7 @@ impl std::fmt::Display for MidiMsgGeneric {
 
 impl From<jack::RawMidi<'_>> for MidiMsgGeneric {
     fn from(midi: jack::RawMidi<'_>) -> MidiMsgGeneric {
-        let mut data: [u8; MAX_MIDI] = [0, 0, 0];
-        data[..MAX_MIDI].copy_from_slice(&midi.bytes[..MAX_MIDI]);
-        MidiMsgGeneric {
-            len: MAX_MIDI,
-            data,
-            time: midi.time as u64 + jack::get_time(),
-        }
+        MidiMsgGeneric::from_bytes(midi.bytes, midi.time as u64 + jack::get_time())
     }
 }
 
@@ -495,98 +501,793 @@ impl std::fmt::Display for MidiMsgPitchBend {
     }
 }
 
-impl From<jack::RawMidi<'_>> for Box<dyn MidiMsgBase> {
-    fn from(midi: jack::RawMidi<'_>) -> Box<dyn MidiMsgBase> {
-        let midi_time = midi.time as u64 + jack::get_time();
-        let len = std::cmp::min(MAX_MIDI, midi.bytes.len());
-        let (status, channel) = from_status_byte(midi.bytes[0]);
-        if status == 0x08 {
-            // NoteOff
-            Box::new(MidiMsgNoteOff {
+pub struct MidiMsgPolyAftertouch {
+    pub channel: u8,
+    pub key: u8,
+    pub pressure: u8,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgPolyAftertouch {
+    fn type_of(&self) -> &str {
+        "MidiMsgPolyAftertouch"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        vec![0xA0 + self.channel, self.key, self.pressure]
+    }
+    fn get_id(&self) -> u16 {
+        0xA000 + ((self.channel as u16) << 8) + self.key as u16
+    }
+    fn get_value(&self) -> u16 {
+        self.pressure as u16
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgPolyAftertouch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiPolyAftertouch: time: {}, channel: {}, key: {}, pressure: {}",
+            self.time, self.channel, self.key, self.pressure,
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgPolyAftertouch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiPolyAftertouch: time: {}, channel: {}, key: {}, pressure: {}",
+            self.time, self.channel, self.key, self.pressure,
+        )
+    }
+}
+
+pub struct MidiMsgProgramChange {
+    pub channel: u8,
+    pub program: u8,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgProgramChange {
+    fn type_of(&self) -> &str {
+        "MidiMsgProgramChange"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        vec![0xC0 + self.channel, self.program]
+    }
+    fn get_id(&self) -> u16 {
+        0xC000 + ((self.channel as u16) << 8)
+    }
+    fn get_value(&self) -> u16 {
+        self.program as u16
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgProgramChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiProgramChange: time: {}, channel: {}, program: {}",
+            self.time, self.channel, self.program,
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgProgramChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiProgramChange: time: {}, channel: {}, program: {}",
+            self.time, self.channel, self.program,
+        )
+    }
+}
+
+pub struct MidiMsgChannelAftertouch {
+    pub channel: u8,
+    pub pressure: u8,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgChannelAftertouch {
+    fn type_of(&self) -> &str {
+        "MidiMsgChannelAftertouch"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        vec![0xD0 + self.channel, self.pressure]
+    }
+    fn get_id(&self) -> u16 {
+        0xD000 + ((self.channel as u16) << 8)
+    }
+    fn get_value(&self) -> u16 {
+        self.pressure as u16
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgChannelAftertouch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiChannelAftertouch: time: {}, channel: {}, pressure: {}",
+            self.time, self.channel, self.pressure,
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgChannelAftertouch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiChannelAftertouch: time: {}, channel: {}, pressure: {}",
+            self.time, self.channel, self.pressure,
+        )
+    }
+}
+
+pub struct MidiMsgSongPosition {
+    pub position: u16,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgSongPosition {
+    fn type_of(&self) -> &str {
+        "MidiMsgSongPosition"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        let (msb_value, lsb_value) = u14_to_msb_lsb(self.position);
+        vec![0xF2, lsb_value, msb_value]
+    }
+    fn get_id(&self) -> u16 {
+        0xF200
+    }
+    fn get_value(&self) -> u16 {
+        self.position
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgSongPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSongPosition: time: {}, position: {}",
+            self.time, self.position,
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgSongPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSongPosition: time: {}, position: {}",
+            self.time, self.position,
+        )
+    }
+}
+
+pub struct MidiMsgSystemRealtime {
+    pub kind: SystemRealtime,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgSystemRealtime {
+    fn type_of(&self) -> &str {
+        "MidiMsgSystemRealtime"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        vec![self.kind.to_status_byte()]
+    }
+    fn get_id(&self) -> u16 {
+        (self.kind.to_status_byte() as u16) << 8
+    }
+    fn get_value(&self) -> u16 {
+        0
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgSystemRealtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSystemRealtime: time: {}, kind: {:?}",
+            self.time, self.kind,
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgSystemRealtime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSystemRealtime: time: {}, kind: {:?}",
+            self.time, self.kind,
+        )
+    }
+}
+
+pub struct MidiMsgSysEx {
+    /// complete message including the 0xF0 and 0xF7 bytes
+    pub data: Vec<u8>,
+    pub time: u64,
+}
+
+impl MidiMsgBase for MidiMsgSysEx {
+    fn type_of(&self) -> &str {
+        "MidiMsgSysEx"
+    }
+    fn get_data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+    fn get_id(&self) -> u16 {
+        0xF000
+    }
+    fn get_value(&self) -> u16 {
+        0
+    }
+    fn get_time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl std::fmt::Debug for MidiMsgSysEx {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSysEx: time: {}, len: {}, data: {:?}",
+            self.time,
+            self.data.len(),
+            self.data
+        )
+    }
+}
+
+impl std::fmt::Display for MidiMsgSysEx {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiSysEx: time: {}, len: {}, data: {:?}",
+            self.time,
+            self.data.len(),
+            self.data
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemRealtime {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+}
+
+impl SystemRealtime {
+    pub fn from_status_byte(status_byte: u8) -> Option<Self> {
+        match status_byte {
+            0xF8 => Some(Self::Clock),
+            0xFA => Some(Self::Start),
+            0xFB => Some(Self::Continue),
+            0xFC => Some(Self::Stop),
+            0xFE => Some(Self::ActiveSensing),
+            0xFF => Some(Self::Reset),
+            _ => None,
+        }
+    }
+
+    pub fn to_status_byte(&self) -> u8 {
+        match self {
+            Self::Clock => 0xF8,
+            Self::Start => 0xFA,
+            Self::Continue => 0xFB,
+            Self::Stop => 0xFC,
+            Self::ActiveSensing => 0xFE,
+            Self::Reset => 0xFF,
+        }
+    }
+}
+
+/// length of a message including the status byte, None for sysex which ends with 0xF7
+pub fn midi_msg_len(status_byte: u8) -> Option<usize> {
+    match status_byte {
+        0x80..=0xBF | 0xE0..=0xEF => Some(3),
+        0xC0..=0xDF => Some(2),
+        0xF0 => None,
+        0xF1 | 0xF3 => Some(2),
+        0xF2 => Some(3),
+        _ => Some(1),
+    }
+}
+
+/// Copy-able decoded message without allocation, usable in the real-time thread.
+/// The payload of sysex and unknown messages stays in the raw bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, key: u8, velocity: u8 },
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    PolyAftertouch { channel: u8, key: u8, pressure: u8 },
+    ControlChange { channel: u8, control: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelAftertouch { channel: u8, pressure: u8 },
+    PitchBend { channel: u8, value: u16 },
+    SongPosition(u16),
+    SystemRealtime(SystemRealtime),
+    SysEx,
+    Unknown,
+}
+
+impl MidiMessage {
+    /// decode one complete message, incomplete or unknown messages are Unknown
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let status_byte = match bytes.first() {
+            Some(status_byte) => *status_byte,
+            None => return Self::Unknown,
+        };
+        if let Some(len) = midi_msg_len(status_byte) {
+            if bytes.len() < len {
+                return Self::Unknown;
+            }
+        }
+        let (status, channel) = from_status_byte(status_byte);
+        match status {
+            0x08 => Self::NoteOff {
                 channel,
-                key: mask7(midi.bytes[1]),
-                velocity: mask7(midi.bytes[2]),
-                time: midi_time,
-            })
-        } else if status == 0x09 {
-            // NoteOn
-            Box::new(MidiMsgNoteOn {
+                key: mask7(bytes[1]),
+                velocity: mask7(bytes[2]),
+            },
+            // a note on with velocity 0 is a note off
+            0x09 if mask7(bytes[2]) == 0 => Self::NoteOff {
                 channel,
-                key: mask7(midi.bytes[1]),
-                velocity: mask7(midi.bytes[2]),
-                time: midi_time,
-            })
-        } else if status == 0x0b {
-            // MidiCC
-            Box::new(MidiMsgControlChange {
+                key: mask7(bytes[1]),
+                velocity: 0,
+            },
+            0x09 => Self::NoteOn {
                 channel,
-                control: mask7(midi.bytes[1]),
-                value: mask7(midi.bytes[2]),
-                time: midi_time,
-            })
-        } else if status == 0x0e {
-            // MidiPitchBend
-            Box::new(MidiMsgPitchBend {
+                key: mask7(bytes[1]),
+                velocity: mask7(bytes[2]),
+            },
+            0x0a => Self::PolyAftertouch {
                 channel,
-                value: msb_lsb_to_u14(mask7(midi.bytes[2]), mask7(midi.bytes[1])),
-                time: midi_time,
-            })
-        } else {
-            let mut data = [0; MAX_MIDI];
-            data[..len].copy_from_slice(&midi.bytes[..len]);
-            Box::new(MidiMsgGeneric {
-                len,
-                data,
-                time: midi_time,
-            })
+                key: mask7(bytes[1]),
+                pressure: mask7(bytes[2]),
+            },
+            0x0b => Self::ControlChange {
+                channel,
+                control: mask7(bytes[1]),
+                value: mask7(bytes[2]),
+            },
+            0x0c => Self::ProgramChange {
+                channel,
+                program: mask7(bytes[1]),
+            },
+            0x0d => Self::ChannelAftertouch {
+                channel,
+                pressure: mask7(bytes[1]),
+            },
+            0x0e => Self::PitchBend {
+                channel,
+                value: msb_lsb_to_u14(mask7(bytes[2]), mask7(bytes[1])),
+            },
+            _ => match status_byte {
+                0xF0 => Self::SysEx,
+                0xF2 => Self::SongPosition(msb_lsb_to_u14(mask7(bytes[2]), mask7(bytes[1]))),
+                _ => match SystemRealtime::from_status_byte(status_byte) {
+                    Some(kind) => Self::SystemRealtime(kind),
+                    None => Self::Unknown,
+                },
+            },
+        }
+    }
+
+    /// same id as get_id of the message structs
+    pub fn get_id(&self) -> u16 {
+        match *self {
+            Self::NoteOff { channel, key, .. } => 0x8000 + ((channel as u16) << 8) + key as u16,
+            Self::NoteOn { channel, key, .. } => 0x9000 + ((channel as u16) << 8) + key as u16,
+            Self::PolyAftertouch { channel, key, .. } => {
+                0xA000 + ((channel as u16) << 8) + key as u16
+            }
+            Self::ControlChange {
+                channel, control, ..
+            } => 0xB000 + ((channel as u16) << 8) + control as u16,
+            Self::ProgramChange { channel, .. } => 0xC000 + ((channel as u16) << 8),
+            Self::ChannelAftertouch { channel, .. } => 0xD000 + ((channel as u16) << 8),
+            Self::PitchBend { channel, .. } => 0xE000 + ((channel as u16) << 8),
+            Self::SongPosition(_) => 0xF200,
+            Self::SystemRealtime(kind) => (kind.to_status_byte() as u16) << 8,
+            Self::SysEx => 0xF000,
+            Self::Unknown => u16::MAX,
+        }
+    }
+
+    /// None for system messages
+    pub fn get_channel(&self) -> Option<u8> {
+        match *self {
+            Self::NoteOff { channel, .. }
+            | Self::NoteOn { channel, .. }
+            | Self::PolyAftertouch { channel, .. }
+            | Self::ControlChange { channel, .. }
+            | Self::ProgramChange { channel, .. }
+            | Self::ChannelAftertouch { channel, .. }
+            | Self::PitchBend { channel, .. } => Some(channel),
+            Self::SongPosition(_) | Self::SystemRealtime(_) | Self::SysEx | Self::Unknown => None,
+        }
+    }
+
+    /// same value as get_value of the message structs
+    pub fn get_value(&self) -> u16 {
+        match *self {
+            Self::NoteOff { velocity, .. } | Self::NoteOn { velocity, .. } => velocity as u16,
+            Self::PolyAftertouch { pressure, .. } | Self::ChannelAftertouch { pressure, .. } => {
+                pressure as u16
+            }
+            Self::ControlChange { value, .. } => value as u16,
+            Self::ProgramChange { program, .. } => program as u16,
+            Self::PitchBend { value, .. } | Self::SongPosition(value) => value,
+            Self::SystemRealtime(_) | Self::SysEx | Self::Unknown => 0,
+        }
+    }
+
+    /// conversion to the message structs, bytes are only read for sysex and unknown messages
+    pub fn to_msg_base(&self, bytes: &[u8], time: u64) -> Box<dyn MidiMsgBase> {
+        match *self {
+            Self::NoteOff {
+                channel,
+                key,
+                velocity,
+            } => Box::new(MidiMsgNoteOff {
+                channel,
+                key,
+                velocity,
+                time,
+            }),
+            Self::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => Box::new(MidiMsgNoteOn {
+                channel,
+                key,
+                velocity,
+                time,
+            }),
+            Self::PolyAftertouch {
+                channel,
+                key,
+                pressure,
+            } => Box::new(MidiMsgPolyAftertouch {
+                channel,
+                key,
+                pressure,
+                time,
+            }),
+            Self::ControlChange {
+                channel,
+                control,
+                value,
+            } => Box::new(MidiMsgControlChange {
+                channel,
+                control,
+                value,
+                time,
+            }),
+            Self::ProgramChange { channel, program } => Box::new(MidiMsgProgramChange {
+                channel,
+                program,
+                time,
+            }),
+            Self::ChannelAftertouch { channel, pressure } => Box::new(MidiMsgChannelAftertouch {
+                channel,
+                pressure,
+                time,
+            }),
+            Self::PitchBend { channel, value } => Box::new(MidiMsgPitchBend {
+                channel,
+                value,
+                time,
+            }),
+            Self::SongPosition(position) => Box::new(MidiMsgSongPosition { position, time }),
+            Self::SystemRealtime(kind) => Box::new(MidiMsgSystemRealtime { kind, time }),
+            Self::SysEx => {
+                let end = bytes
+                    .iter()
+                    .position(|byte| *byte == 0xF7)
+                    .map_or(bytes.len(), |pos| pos + 1);
+                Box::new(MidiMsgSysEx {
+                    data: bytes[..end].to_vec(),
+                    time,
+                })
+            }
+            Self::Unknown => Box::new(MidiMsgGeneric::from_bytes(bytes, time)),
+        }
+    }
+}
+
+impl From<&dyn MidiMsgBase> for MidiMessage {
+    fn from(midi_msg: &dyn MidiMsgBase) -> MidiMessage {
+        MidiMessage::from_bytes(&midi_msg.get_data())
+    }
+}
+
+/// decode one complete message, incomplete or unknown messages end up as MidiMsgGeneric
+pub fn decode_midi_bytes(bytes: &[u8], time: u64) -> Box<dyn MidiMsgBase> {
+    MidiMessage::from_bytes(bytes).to_msg_base(bytes, time)
+}
+
+/// Decoder for a stream of messages that may use running status:
+/// channel messages without status byte reuse the last channel status byte.
+#[derive(Default)]
+pub struct MidiDecoder {
+    running_status: Option<u8>,
+}
+
+impl MidiDecoder {
+    pub fn new() -> Self {
+        MidiDecoder {
+            running_status: None,
+        }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Option<MidiMessage> {
+        let first_byte = *bytes.first()?;
+        if first_byte < 0x80 {
+            let running_status = self.running_status?;
+            let len = std::cmp::min(MAX_MIDI, bytes.len() + 1);
+            let mut data = [running_status, 0, 0];
+            data[1..len].copy_from_slice(&bytes[..len - 1]);
+            return Some(MidiMessage::from_bytes(&data[..len]));
+        }
+        match first_byte {
+            0x80..=0xEF => self.running_status = Some(first_byte),
+            // realtime messages may appear in between and keep the running status
+            0xF8..=0xFF => (),
+            _ => self.running_status = None,
         }
+        Some(MidiMessage::from_bytes(bytes))
+    }
+}
+
+impl From<jack::RawMidi<'_>> for Box<dyn MidiMsgBase> {
+    fn from(midi: jack::RawMidi<'_>) -> Box<dyn MidiMsgBase> {
+        decode_midi_bytes(midi.bytes, midi.time as u64 + jack::get_time())
     }
 }
 
 impl From<MidiMsgGeneric> for Box<dyn MidiMsgBase> {
     fn from(midi: MidiMsgGeneric) -> Box<dyn MidiMsgBase> {
-        let midi_time = midi.time;
-        let len = midi.len;
-        let (status, channel) = from_status_byte(midi.data[0]);
-        if status == 0x08 {
-            // NoteOff
-            Box::new(MidiMsgNoteOff {
-                channel,
-                key: mask7(midi.data[1]),
-                velocity: mask7(midi.data[2]),
-                time: midi_time,
-            })
-        } else if status == 0x09 {
-            // NoteOn
-            Box::new(MidiMsgNoteOn {
-                channel,
-                key: mask7(midi.data[1]),
-                velocity: mask7(midi.data[2]),
-                time: midi_time,
+        decode_midi_bytes(midi.get_bytes(), midi.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msg_len() {
+        assert_eq!(midi_msg_len(0x90), Some(3));
+        assert_eq!(midi_msg_len(0xC3), Some(2));
+        assert_eq!(midi_msg_len(0xD0), Some(2));
+        assert_eq!(midi_msg_len(0xF0), None);
+        assert_eq!(midi_msg_len(0xF2), Some(3));
+        assert_eq!(midi_msg_len(0xF3), Some(2));
+        assert_eq!(midi_msg_len(0xF8), Some(1));
+    }
+
+    #[test]
+    fn program_change_and_aftertouch() {
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xC2, 0x05]),
+            MidiMessage::ProgramChange {
+                channel: 2,
+                program: 5
+            }
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xD1, 0x40]),
+            MidiMessage::ChannelAftertouch {
+                channel: 1,
+                pressure: 0x40
+            }
+        );
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xA0, 0x3C, 0x7F]),
+            MidiMessage::PolyAftertouch {
+                channel: 0,
+                key: 0x3C,
+                pressure: 0x7F
+            }
+        );
+        let program_change = decode_midi_bytes(&[0xC2, 0x05], 7);
+        assert_eq!(program_change.type_of(), "MidiMsgProgramChange");
+        assert_eq!(program_change.get_value(), 5);
+        assert_eq!(program_change.get_time(), 7);
+        assert_eq!(
+            decode_midi_bytes(&[0xD1, 0x40], 0).type_of(),
+            "MidiMsgChannelAftertouch"
+        );
+        assert_eq!(
+            decode_midi_bytes(&[0xA0, 0x3C, 0x7F], 0).type_of(),
+            "MidiMsgPolyAftertouch"
+        );
+    }
+
+    #[test]
+    fn note_on_velocity_zero_is_note_off() {
+        assert_eq!(
+            MidiMessage::from_bytes(&[0x93, 0x3C, 0x00]),
+            MidiMessage::NoteOff {
+                channel: 3,
+                key: 0x3C,
+                velocity: 0
+            }
+        );
+        assert_eq!(
+            decode_midi_bytes(&[0x93, 0x3C, 0x00], 0).type_of(),
+            "MidiMsgNoteOff"
+        );
+    }
+
+    #[test]
+    fn system_realtime() {
+        for (status_byte, kind) in [
+            (0xF8, SystemRealtime::Clock),
+            (0xFA, SystemRealtime::Start),
+            (0xFB, SystemRealtime::Continue),
+            (0xFC, SystemRealtime::Stop),
+        ] {
+            assert_eq!(
+                MidiMessage::from_bytes(&[status_byte]),
+                MidiMessage::SystemRealtime(kind)
+            );
+            assert_eq!(kind.to_status_byte(), status_byte);
+            let midi_msg = decode_midi_bytes(&[status_byte], 0);
+            assert_eq!(midi_msg.type_of(), "MidiMsgSystemRealtime");
+            assert_eq!(midi_msg.get_data(), vec![status_byte]);
+        }
+    }
+
+    #[test]
+    fn song_position() {
+        // lsb first, 0x10 << 7 + 0x05
+        assert_eq!(
+            MidiMessage::from_bytes(&[0xF2, 0x05, 0x10]),
+            MidiMessage::SongPosition(0x0805)
+        );
+        let midi_msg = decode_midi_bytes(&[0xF2, 0x05, 0x10], 0);
+        assert_eq!(midi_msg.type_of(), "MidiMsgSongPosition");
+        assert_eq!(midi_msg.get_value(), 0x0805);
+    }
+
+    #[test]
+    fn sysex() {
+        let bytes = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        assert_eq!(MidiMessage::from_bytes(&bytes), MidiMessage::SysEx);
+        let midi_msg = decode_midi_bytes(&bytes, 0);
+        assert_eq!(midi_msg.type_of(), "MidiMsgSysEx");
+        assert_eq!(midi_msg.get_data(), bytes.to_vec());
+        // bytes after the end of exclusive are not part of the message
+        let midi_msg = decode_midi_bytes(&[0xF0, 0x01, 0xF7, 0xF8], 0);
+        assert_eq!(midi_msg.get_data(), vec![0xF0, 0x01, 0xF7]);
+    }
+
+    #[test]
+    fn sysex_unterminated() {
+        let bytes = [0xF0, 0x43, 0x12];
+        assert_eq!(MidiMessage::from_bytes(&bytes), MidiMessage::SysEx);
+        let midi_msg = decode_midi_bytes(&bytes, 0);
+        assert_eq!(midi_msg.type_of(), "MidiMsgSysEx");
+        assert_eq!(midi_msg.get_data(), bytes.to_vec());
+    }
+
+    #[test]
+    fn truncated_input() {
+        assert_eq!(MidiMessage::from_bytes(&[]), MidiMessage::Unknown);
+        for bytes in [
+            &[0x90][..],
+            &[0x90, 0x3C],
+            &[0xB0, 0x07],
+            &[0xE0, 0x00],
+            &[0xC0],
+            &[0xF2, 0x01],
+            &[0xF3],
+        ] {
+            assert_eq!(MidiMessage::from_bytes(bytes), MidiMessage::Unknown);
+            let midi_msg = decode_midi_bytes(bytes, 0);
+            assert_eq!(midi_msg.type_of(), "MidiMsgGeneric");
+            assert_eq!(midi_msg.get_data(), bytes.to_vec());
+        }
+        let mut decoder = MidiDecoder::new();
+        assert_eq!(decoder.decode(&[]), None);
+        // data bytes without running status
+        assert_eq!(decoder.decode(&[0x3C]), None);
+        assert_eq!(decoder.decode(&[0x90]), Some(MidiMessage::Unknown));
+        assert_eq!(decoder.decode(&[0x3C]), Some(MidiMessage::Unknown));
+    }
+
+    #[test]
+    fn running_status() {
+        let mut decoder = MidiDecoder::new();
+        assert_eq!(
+            decoder.decode(&[0x91, 0x3C, 0x64]),
+            Some(MidiMessage::NoteOn {
+                channel: 1,
+                key: 0x3C,
+                velocity: 0x64
             })
-        } else if status == 0x0b {
-            // MidiCC
-            Box::new(MidiMsgControlChange {
-                channel,
-                control: mask7(midi.data[1]),
-                value: mask7(midi.data[2]),
-                time: midi_time,
+        );
+        assert_eq!(
+            decoder.decode(&[0x40, 0x50]),
+            Some(MidiMessage::NoteOn {
+                channel: 1,
+                key: 0x40,
+                velocity: 0x50
             })
-        } else if status == 0x0e {
-            // MidiPitchBend
-            Box::new(MidiMsgPitchBend {
-                channel,
-                value: msb_lsb_to_u14(mask7(midi.data[2]), mask7(midi.data[1])),
-                time: midi_time,
+        );
+        assert_eq!(
+            decoder.decode(&[0x3C, 0x00]),
+            Some(MidiMessage::NoteOff {
+                channel: 1,
+                key: 0x3C,
+                velocity: 0
             })
-        } else {
-            let mut data = [0; MAX_MIDI];
-            data[..len].copy_from_slice(&midi.data[..len]);
-            Box::new(MidiMsgGeneric {
-                len,
-                data,
-                time: midi_time,
+        );
+        // two byte messages
+        decoder.decode(&[0xC0, 0x01]);
+        assert_eq!(
+            decoder.decode(&[0x02]),
+            Some(MidiMessage::ProgramChange {
+                channel: 0,
+                program: 2
             })
+        );
+    }
+
+    #[test]
+    fn running_status_with_realtime() {
+        let mut decoder = MidiDecoder::new();
+        decoder.decode(&[0xB0, 0x07, 0x10]);
+        for status_byte in [0xF8, 0xFA, 0xFB, 0xFC, 0xFE] {
+            assert!(matches!(
+                decoder.decode(&[status_byte]),
+                Some(MidiMessage::SystemRealtime(_))
+            ));
+            assert_eq!(
+                decoder.decode(&[0x07, 0x20]),
+                Some(MidiMessage::ControlChange {
+                    channel: 0,
+                    control: 0x07,
+                    value: 0x20
+                })
+            );
         }
     }
+
+    #[test]
+    fn system_common_clears_running_status() {
+        let mut decoder = MidiDecoder::new();
+        decoder.decode(&[0x90, 0x3C, 0x64]);
+        decoder.decode(&[0xF0, 0x01, 0xF7]);
+        assert_eq!(decoder.decode(&[0x3C, 0x64]), None);
+        decoder.decode(&[0x90, 0x3C, 0x64]);
+        decoder.decode(&[0xF2, 0x00, 0x00]);
+        assert_eq!(decoder.decode(&[0x3C, 0x64]), None);
+    }
 }
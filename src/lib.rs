@@ -1,16 +1,37 @@
 pub mod adsr;
+pub mod autopan;
+pub mod biquad;
+pub mod bound_notes;
+pub mod control_change;
+pub mod controller_profile;
 pub mod ctrl_msg;
 pub mod delay;
 pub mod effect;
+pub mod engine;
+pub mod equalizer;
+pub mod file_watcher;
 pub mod jackaudio;
 pub mod jackmidi;
 pub mod midi_functions;
+pub mod midi_mapping;
+pub mod midi_monitor;
+pub mod midi_out;
+pub mod midi_player;
 pub mod midi_process;
 pub mod overdrive;
+pub mod part;
+pub mod patch;
+pub mod pedal;
+pub mod recorder;
+pub mod scope;
+pub mod smf;
+pub mod spectrum;
+pub mod stereo;
 pub mod tone;
 pub mod tone_handling;
 pub mod tone_map;
 pub mod trigger_note_msg;
 pub mod util;
+pub mod wav;
 pub mod wave;
 pub mod wave_gen;
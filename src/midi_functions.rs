@@ -1,24 +1,393 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::f32::consts::TAU;
 use std::io::Read;
 use std::result::Result;
 use std::{fmt, fs::File};
 
 use crate::jackmidi::MidiMsgAdvanced;
+use crate::midi_mapping::{parse_midi_mapping, to_midi_mapping_file};
 
-#[derive(Hash, Eq, PartialEq, Serialize, Deserialize, Clone, Debug)]
+/// range of a function if the binding has no min/max, (0.0, 1.0) for unknown functions
+pub fn default_midi_function_range(name: &str) -> (f32, f32) {
+    match name {
+        "freq_am" | "freq_fm" | "overdrive_gain" | "vibrato_rate" => (0.0, 10.0),
+        "phase_am" | "phase_fm" => (0.0, TAU),
+        "intensity_fm" => (0.0, 100.0),
+        "pan" | "pitch_bend" => (-1.0, 1.0),
+        "pitch_bend_range" => (0.0, 24.0),
+        "stereo_width" | "vibrato_depth" => (0.0, 2.0),
+        _ => (0.0, 1.0),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiCurve {
+    #[default]
+    Linear,
+    /// fast rise at the start of the controller travel
+    Log,
+    /// slow rise at the start of the controller travel
+    Exp,
+}
+
+impl MidiCurve {
+    fn is_linear(&self) -> bool {
+        *self == Self::Linear
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        match self {
+            Self::Linear => x,
+            Self::Log => (1.0 + 9.0 * x).log10(),
+            Self::Exp => (10f32.powf(x) - 1.0) / 9.0,
+        }
+    }
+
+    fn inverse(&self, y: f32) -> f32 {
+        match self {
+            Self::Linear => y,
+            Self::Log => (10f32.powf(y) - 1.0) / 9.0,
+            Self::Exp => (1.0 + 9.0 * y).log10(),
+        }
+    }
+}
+
+/// How a 7 bit controller encodes its value. Endless encoders send the movement
+/// since the last message instead of a position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiEncoder {
+    #[default]
+    Absolute,
+    /// 1..=63 up, 127 down by 1 (65..=127 down)
+    TwosComplement,
+    /// 65..=127 up, 63 down by 1 (0..=63 down)
+    BinaryOffset,
+    /// bit 6 set is down, the magnitude in the lower 6 bits
+    SignMagnitude,
+}
+
+impl MidiEncoder {
+    fn is_absolute(&self) -> bool {
+        *self == Self::Absolute
+    }
+
+    /// steps a relative encoder moved, None for absolute controllers
+    pub fn delta(&self, value: u8) -> Option<i32> {
+        let value = (value & 0x7f) as i32;
+        match self {
+            Self::Absolute => None,
+            Self::TwosComplement => Some(if value < 64 { value } else { value - 128 }),
+            Self::BinaryOffset => Some(value - 64),
+            Self::SignMagnitude => Some(if value & 0x40 != 0 {
+                -(value & 0x3f)
+            } else {
+                value
+            }),
+        }
+    }
+}
+
+/// What an absolute controller does when its position differs from the value of the function,
+/// e.g. after a patch change or a change in the gui.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiTakeover {
+    /// the value jumps to the controller position
+    #[default]
+    Jump,
+    /// the value stays until the controller reaches or crosses it
+    Pickup,
+    /// the value moves towards the end the controller moves to, proportional to the remaining travel,
+    /// until both meet
+    Scale,
+}
+
+impl MidiTakeover {
+    fn is_jump(&self) -> bool {
+        *self == Self::Jump
+    }
+}
+
+/// controller travel within which a controller picks up the value
+const TAKEOVER_TOLERANCE: f32 = 1.0 / 127.0;
+/// controller travel below which a function value counts as unchanged, far below one controller step
+const TAKEOVER_VALUE_TOLERANCE: f32 = 1e-4;
+
+/// Takeover of one binding, kept between its messages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MidiTakeoverState {
+    /// last controller position
+    norm_value: Option<f32>,
+    /// last value sent to the function
+    value: Option<f32>,
+    picked_up: bool,
+}
+
+fn is_default_sensitivity(sensitivity: &f32) -> bool {
+    *sensitivity == 1.0
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+fn is_continuous(steps: &u32) -> bool {
+    *steps == 0
+}
+
+/// Mapping of a normalized controller value (0.0..=1.0) to the value of a function.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiScaling {
+    /// None for the default range of the function
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub invert: bool,
+    #[serde(skip_serializing_if = "MidiCurve::is_linear")]
+    pub curve: MidiCurve,
+    /// number of discrete values, 0 or 1 for continuous
+    #[serde(skip_serializing_if = "is_continuous")]
+    pub steps: u32,
+    /// controller travel at both ends that is clamped to min and max, 0.0..0.5
+    #[serde(skip_serializing_if = "is_zero")]
+    pub deadzone: f32,
+    #[serde(skip_serializing_if = "MidiEncoder::is_absolute")]
+    pub encoder: MidiEncoder,
+    /// relative encoders: controller travel per step in 1/127
+    #[serde(skip_serializing_if = "is_default_sensitivity")]
+    pub sensitivity: f32,
+    /// relative encoders: bigger steps from fast turns move over-proportionally, 0.0 for linear
+    #[serde(skip_serializing_if = "is_zero")]
+    pub acceleration: f32,
+    /// absolute controllers: jump, pickup or scale to the current value
+    #[serde(skip_serializing_if = "MidiTakeover::is_jump")]
+    pub takeover: MidiTakeover,
+}
+
+impl Default for MidiScaling {
+    fn default() -> Self {
+        MidiScaling {
+            min: None,
+            max: None,
+            invert: false,
+            curve: MidiCurve::Linear,
+            steps: 0,
+            deadzone: 0.0,
+            encoder: MidiEncoder::Absolute,
+            sensitivity: 1.0,
+            acceleration: 0.0,
+            takeover: MidiTakeover::Jump,
+        }
+    }
+}
+
+impl MidiScaling {
+    fn get_range(&self, default_range: (f32, f32)) -> (f32, f32) {
+        (
+            self.min.unwrap_or(default_range.0),
+            self.max.unwrap_or(default_range.1),
+        )
+    }
+
+    /// deadzone, invert, curve and steps, then into the range
+    pub fn scale(&self, norm_value: f32, default_range: (f32, f32)) -> f32 {
+        let deadzone = self.deadzone.clamp(0.0, 0.49);
+        let mut x = ((norm_value - deadzone) / (1.0 - 2.0 * deadzone)).clamp(0.0, 1.0);
+        if self.invert {
+            x = 1.0 - x;
+        }
+        x = self.curve.apply(x);
+        if self.steps > 1 {
+            let intervals = (self.steps - 1) as f32;
+            x = (x * intervals).round() / intervals;
+        }
+        let (min, max) = self.get_range(default_range);
+        min + x * (max - min)
+    }
+
+    /// normalized controller value for a function value, e.g. for controller feedback
+    pub fn unscale(&self, value: f32, default_range: (f32, f32)) -> f32 {
+        let (min, max) = self.get_range(default_range);
+        if max == min {
+            return 0.0;
+        }
+        let mut x = self
+            .curve
+            .inverse(((value - min) / (max - min)).clamp(0.0, 1.0));
+        if self.invert {
+            x = 1.0 - x;
+        }
+        let deadzone = self.deadzone.clamp(0.0, 0.49);
+        deadzone + x * (1.0 - 2.0 * deadzone)
+    }
+
+    /// value after a relative encoder moved delta steps from value
+    pub fn scale_relative(&self, value: f32, delta: i32, default_range: (f32, f32)) -> f32 {
+        if delta == 0 {
+            return value;
+        }
+        let magnitude = (delta.unsigned_abs() as f32).powf(1.0 + self.acceleration.max(0.0));
+        let mut norm_delta = magnitude * self.sensitivity / 127.0;
+        if self.steps > 1 {
+            // at least one discrete value per step
+            norm_delta = norm_delta.max(1.0 / (self.steps - 1) as f32);
+        }
+        let deadzone = self.deadzone.clamp(0.0, 0.49);
+        let norm_value = self.unscale(value, default_range)
+            + norm_delta.copysign(delta as f32) * (1.0 - 2.0 * deadzone);
+        self.scale(norm_value.clamp(deadzone, 1.0 - deadzone), default_range)
+    }
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
 pub struct MidiFunction {
     name: String,
-    // ToDo: invert, log,linear, scaling..
+    #[serde(default)]
+    scaling: MidiScaling,
 }
 
 impl MidiFunction {
     pub fn new(name: String) -> Self {
-        MidiFunction { name }
+        MidiFunction {
+            name,
+            scaling: MidiScaling::default(),
+        }
+    }
+    pub fn with_scaling(name: String, scaling: MidiScaling) -> Self {
+        MidiFunction { name, scaling }
     }
     pub fn get_name(&self) -> String {
         self.name.as_str().to_string()
     }
+    pub fn get_scaling(&self) -> MidiScaling {
+        self.scaling
+    }
+    /// value of the function for a normalized controller value
+    pub fn scale(&self, norm_value: f32) -> f32 {
+        self.scaling
+            .scale(norm_value, default_midi_function_range(&self.name))
+    }
+    pub fn unscale(&self, value: f32) -> f32 {
+        self.scaling
+            .unscale(value, default_midi_function_range(&self.name))
+    }
+    /// Value of the function for a controller at norm_value when the function is at value,
+    /// None while the controller has not taken over.
+    pub fn take_over(
+        &self,
+        value: f32,
+        norm_value: f32,
+        state: &mut MidiTakeoverState,
+    ) -> Option<f32> {
+        let last_norm_value = state.norm_value.replace(norm_value);
+        // changed by the gui, a patch or another controller since the last message,
+        // values that went through f32 conversions again count as unchanged
+        let changed = state.value.map_or(true, |last_value| {
+            (self.unscale(last_value) - self.unscale(value)).abs() > TAKEOVER_VALUE_TOLERANCE
+        });
+        if changed {
+            state.picked_up = false;
+        }
+        if self.scaling.takeover == MidiTakeover::Jump || state.picked_up {
+            state.value = Some(self.scale(norm_value));
+            return state.value;
+        }
+        let current = self.unscale(value);
+        let crossed =
+            last_norm_value.is_some_and(|last| (last - current) * (norm_value - current) <= 0.0);
+        let norm_value = match self.scaling.takeover {
+            MidiTakeover::Pickup => {
+                if crossed || (norm_value - current).abs() <= TAKEOVER_TOLERANCE {
+                    state.picked_up = true;
+                }
+                state.picked_up.then_some(norm_value)
+            }
+            _ => {
+                let last = last_norm_value?;
+                let scaled = if norm_value > last {
+                    current + (norm_value - last) * (1.0 - current) / (1.0 - last).max(f32::EPSILON)
+                } else {
+                    current - (last - norm_value) * current / last.max(f32::EPSILON)
+                };
+                if crossed || (norm_value - scaled).abs() <= TAKEOVER_TOLERANCE {
+                    state.picked_up = true;
+                    Some(norm_value)
+                } else {
+                    Some(scaled.clamp(0.0, 1.0))
+                }
+            }
+        }?;
+        state.value = Some(self.scale(norm_value));
+        state.value
+    }
+    /// value of the function after a relative encoder message, None for absolute controllers
+    pub fn scale_relative(&self, value: f32, midi_advanced_msg: &MidiMsgAdvanced) -> Option<f32> {
+        let MidiMsgAdvanced::MidiControlIdValue(_, controller_value) = *midi_advanced_msg else {
+            return None;
+        };
+        let delta = self.scaling.encoder.delta(controller_value as u8)?;
+        Some(
+            self.scaling
+                .scale_relative(value, delta, default_midi_function_range(&self.name)),
+        )
+    }
+}
+
+/// A controller message bound to a function. In the mapping file either the plain
+/// message or an object with the message in "msg" and the fields of MidiScaling.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "MidiBindingRepr", into = "MidiBindingRepr")]
+pub struct MidiBinding {
+    pub msg: MidiMsgAdvanced,
+    pub scaling: MidiScaling,
+}
+
+impl MidiBinding {
+    pub fn new(msg: MidiMsgAdvanced) -> Self {
+        MidiBinding {
+            msg,
+            scaling: MidiScaling::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MidiBindingRepr {
+    Plain(MidiMsgAdvanced),
+    Scaled {
+        msg: MidiMsgAdvanced,
+        #[serde(flatten)]
+        scaling: MidiScaling,
+    },
+}
+
+impl From<MidiBindingRepr> for MidiBinding {
+    fn from(repr: MidiBindingRepr) -> Self {
+        match repr {
+            MidiBindingRepr::Plain(msg) => MidiBinding::new(msg),
+            MidiBindingRepr::Scaled { msg, scaling } => MidiBinding { msg, scaling },
+        }
+    }
+}
+
+impl From<MidiBinding> for MidiBindingRepr {
+    fn from(binding: MidiBinding) -> Self {
+        if binding.scaling == MidiScaling::default() {
+            MidiBindingRepr::Plain(binding.msg)
+        } else {
+            MidiBindingRepr::Scaled {
+                msg: binding.msg,
+                scaling: binding.scaling,
+            }
+        }
+    }
 }
 
 impl fmt::Display for MidiFunction {
@@ -62,40 +431,350 @@ pub fn parse_json_file_to_midi_functions_with_elements_ids(
     file_content
         .read_to_string(&mut contents)
         .map_err(|err| format!("Could not read file to string {}", err))?;
-    let map: HashMap<String, Vec<u16>> = serde_json::from_str(contents.as_str()).unwrap();
+    let map: HashMap<String, Vec<u16>> = serde_json::from_str(contents.as_str())
+        .map_err(|err| format!("error in json deserialize {}", err))?;
     Ok(map)
 }
 
+/// readable versioned mapping or the old id based one, see parse_midi_mapping
 pub fn parse_json_file_to_midi_functions_with_midi_msgs_advanced(
     file_path_str: &String,
-) -> Result<HashMap<String, Vec<MidiMsgAdvanced>>, String> {
+) -> Result<HashMap<String, Vec<MidiBinding>>, String> {
     let mut file_content =
         File::open(file_path_str).map_err(|err| format!("Could not read the json file {}", err))?;
     let mut contents = String::new();
     file_content
         .read_to_string(&mut contents)
         .map_err(|err| format!("Could not read file to string {}", err))?;
-    let map: HashMap<String, Vec<MidiMsgAdvanced>> =
-        serde_json::from_str(contents.as_str()).unwrap();
-    Ok(map)
+    parse_midi_mapping(contents.as_str()).map_err(|err| format!("{}: {}", file_path_str, err))
+}
+
+pub fn write_midi_functions_with_midi_msgs_advanced_to_json_file(
+    midi_functions_with_midi_advanced_msgs: &HashMap<String, Vec<MidiBinding>>,
+    file_path_str: &str,
+) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(&to_midi_mapping_file(
+        midi_functions_with_midi_advanced_msgs,
+    ))
+    .map_err(|err| format!("error in json serialize {}", err))?;
+    std::fs::write(file_path_str, contents)
+        .map_err(|err| format!("Could not write the json file {}", err))
 }
 
 pub fn reverse_map_midi_functions2midi_advanced_msgs(
-    midi_functions_with_midi_advanced_msgs: HashMap<String, Vec<MidiMsgAdvanced>>,
-) -> HashMap<MidiMsgAdvanced, Vec<String>> {
-    let mut midi_advanced_msgs2midi_functions: HashMap<MidiMsgAdvanced, Vec<String>> =
+    midi_functions_with_midi_advanced_msgs: HashMap<String, Vec<MidiBinding>>,
+) -> HashMap<MidiMsgAdvanced, Vec<MidiFunction>> {
+    let mut midi_advanced_msgs2midi_functions: HashMap<MidiMsgAdvanced, Vec<MidiFunction>> =
         HashMap::new();
     for (key, value_vec) in midi_functions_with_midi_advanced_msgs {
-        let key_insert = key.clone();
         for value in value_vec {
+            let midi_function = MidiFunction::with_scaling(key.clone(), value.scaling);
             if let Some(ref mut midi_function_vec) =
-                midi_advanced_msgs2midi_functions.get_mut(&value)
+                midi_advanced_msgs2midi_functions.get_mut(&value.msg)
             {
-                midi_function_vec.push(key_insert.clone());
+                midi_function_vec.push(midi_function);
             } else {
-                midi_advanced_msgs2midi_functions.insert(value, vec![key_insert.clone()]);
+                midi_advanced_msgs2midi_functions.insert(value.msg, vec![midi_function]);
             }
         }
     }
     midi_advanced_msgs2midi_functions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < EPSILON,
+            "{} != {}",
+            value,
+            expected
+        );
+    }
+
+    fn encoder_scaling(encoder: MidiEncoder) -> MidiScaling {
+        MidiScaling {
+            encoder,
+            ..MidiScaling::default()
+        }
+    }
+
+    #[test]
+    fn encoder_delta() {
+        assert_eq!(MidiEncoder::Absolute.delta(65), None);
+        for (value, delta) in [(0, 0), (1, 1), (63, 63), (64, -64), (65, -63), (127, -1)] {
+            assert_eq!(MidiEncoder::TwosComplement.delta(value), Some(delta));
+        }
+        for (value, delta) in [(0, -64), (1, -63), (63, -1), (64, 0), (65, 1), (127, 63)] {
+            assert_eq!(MidiEncoder::BinaryOffset.delta(value), Some(delta));
+        }
+        for (value, delta) in [(0, 0), (1, 1), (63, 63), (64, 0), (65, -1), (127, -63)] {
+            assert_eq!(MidiEncoder::SignMagnitude.delta(value), Some(delta));
+        }
+        // the status bit of a data byte is ignored
+        assert_eq!(MidiEncoder::TwosComplement.delta(0x81), Some(1));
+    }
+
+    #[test]
+    fn scale_relative_linear() {
+        let scaling = encoder_scaling(MidiEncoder::TwosComplement);
+        assert_close(scaling.scale_relative(0.5, 0, (0.0, 1.0)), 0.5);
+        assert_close(
+            scaling.scale_relative(0.5, 1, (0.0, 1.0)),
+            0.5 + 1.0 / 127.0,
+        );
+        assert_close(
+            scaling.scale_relative(0.5, -3, (0.0, 1.0)),
+            0.5 - 3.0 / 127.0,
+        );
+        assert_close(
+            scaling.scale_relative(5.0, 1, (0.0, 10.0)),
+            5.0 + 10.0 / 127.0,
+        );
+        // clamped at the ends of the range
+        assert_close(scaling.scale_relative(0.99, 10, (0.0, 1.0)), 1.0);
+        assert_close(scaling.scale_relative(0.01, -10, (0.0, 1.0)), 0.0);
+        let scaling = MidiScaling {
+            sensitivity: 2.0,
+            ..scaling
+        };
+        assert_close(
+            scaling.scale_relative(0.5, 1, (0.0, 1.0)),
+            0.5 + 2.0 / 127.0,
+        );
+    }
+
+    #[test]
+    fn scale_relative_acceleration() {
+        let scaling = MidiScaling {
+            acceleration: 1.0,
+            ..encoder_scaling(MidiEncoder::BinaryOffset)
+        };
+        // single steps stay fine, fast turns move by delta^2
+        assert_close(
+            scaling.scale_relative(0.5, 1, (0.0, 1.0)),
+            0.5 + 1.0 / 127.0,
+        );
+        assert_close(
+            scaling.scale_relative(0.5, 4, (0.0, 1.0)),
+            0.5 + 16.0 / 127.0,
+        );
+        assert_close(
+            scaling.scale_relative(0.5, -4, (0.0, 1.0)),
+            0.5 - 16.0 / 127.0,
+        );
+    }
+
+    #[test]
+    fn scale_relative_steps() {
+        let scaling = MidiScaling {
+            steps: 5,
+            ..encoder_scaling(MidiEncoder::TwosComplement)
+        };
+        // every encoder step moves at least one discrete value
+        assert_close(scaling.scale_relative(0.5, 1, (0.0, 1.0)), 0.75);
+        assert_close(scaling.scale_relative(0.5, -1, (0.0, 1.0)), 0.25);
+        assert_close(scaling.scale_relative(0.75, 1, (0.0, 1.0)), 1.0);
+        assert_close(scaling.scale_relative(1.0, 1, (0.0, 1.0)), 1.0);
+        assert_close(scaling.scale_relative(0.0, 40, (0.0, 1.0)), 0.25);
+        assert_close(scaling.scale_relative(0.0, 64, (0.0, 1.0)), 0.5);
+    }
+
+    #[test]
+    fn function_scale_relative() {
+        let function = MidiFunction::with_scaling(
+            "pan".to_string(),
+            encoder_scaling(MidiEncoder::TwosComplement),
+        );
+        let down = MidiMsgAdvanced::MidiControlIdValue(0xB010, 127);
+        // pan is -1.0..=1.0
+        assert_close(function.scale_relative(0.0, &down).unwrap(), -2.0 / 127.0);
+        let absolute = MidiFunction::new("pan".to_string());
+        assert_eq!(absolute.scale_relative(0.0, &down), None);
+        let note = MidiMsgAdvanced::MidiNoteOnOff(0x903C, 0x803C, true, 60, 100);
+        assert_eq!(function.scale_relative(0.0, &note), None);
+    }
+
+    fn takeover_function(takeover: MidiTakeover) -> MidiFunction {
+        MidiFunction::with_scaling(
+            "intensity_am".to_string(),
+            MidiScaling {
+                takeover,
+                ..MidiScaling::default()
+            },
+        )
+    }
+
+    #[test]
+    fn takeover_jump() {
+        let function = takeover_function(MidiTakeover::Jump);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.1, &mut state), Some(0.1));
+        assert_eq!(function.take_over(0.9, 0.2, &mut state), Some(0.2));
+    }
+
+    #[test]
+    fn takeover_pickup_crossing() {
+        let function = takeover_function(MidiTakeover::Pickup);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.2, &mut state), None);
+        assert_eq!(function.take_over(0.5, 0.3, &mut state), None);
+        // a fast move jumps over the value
+        assert_eq!(function.take_over(0.5, 0.7, &mut state), Some(0.7));
+        assert_eq!(function.take_over(0.7, 0.6, &mut state), Some(0.6));
+        // the knob at the upper end crosses when it reaches the value
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(1.0, 0.9, &mut state), None);
+        assert_eq!(function.take_over(1.0, 1.0, &mut state), Some(1.0));
+    }
+
+    #[test]
+    fn takeover_pickup_tolerance() {
+        let function = takeover_function(MidiTakeover::Pickup);
+        let mut state = MidiTakeoverState::default();
+        // the first message can not cross, it picks up next to the value
+        assert_eq!(
+            function.take_over(0.5, 0.5 + 0.5 / 127.0, &mut state),
+            Some(0.5 + 0.5 / 127.0)
+        );
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.5 + 2.0 / 127.0, &mut state), None);
+    }
+
+    #[test]
+    fn takeover_scale_up() {
+        let function = takeover_function(MidiTakeover::Scale);
+        let mut state = MidiTakeoverState::default();
+        // no direction on the first message
+        assert_eq!(function.take_over(0.5, 0.2, &mut state), None);
+        // a quarter of the remaining knob travel moves a quarter of the remaining value travel
+        let value = function.take_over(0.5, 0.4, &mut state).unwrap();
+        assert_close(value, 0.625);
+        // both meet at the end
+        let value = function.take_over(value, 1.0, &mut state).unwrap();
+        assert_close(value, 1.0);
+        assert_eq!(function.take_over(value, 0.8, &mut state), Some(0.8));
+    }
+
+    #[test]
+    fn takeover_scale_down() {
+        let function = takeover_function(MidiTakeover::Scale);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.8, &mut state), None);
+        let value = function.take_over(0.5, 0.6, &mut state).unwrap();
+        assert_close(value, 0.375);
+        let value = function.take_over(value, 0.0, &mut state).unwrap();
+        assert_close(value, 0.0);
+        assert_eq!(function.take_over(value, 0.3, &mut state), Some(0.3));
+    }
+
+    #[test]
+    fn takeover_scale_from_the_ends() {
+        let function = takeover_function(MidiTakeover::Scale);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 1.0, &mut state), None);
+        // no move from the upper end keeps the value
+        assert_eq!(function.take_over(0.5, 1.0, &mut state), Some(0.5));
+        let value = function.take_over(0.5, 0.9, &mut state).unwrap();
+        assert_close(value, 0.45);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.0, &mut state), None);
+        let value = function.take_over(0.5, 0.1, &mut state).unwrap();
+        assert_close(value, 0.55);
+    }
+
+    #[test]
+    fn takeover_gui_change_resets_pickup() {
+        let function = takeover_function(MidiTakeover::Pickup);
+        let mut state = MidiTakeoverState::default();
+        assert_eq!(function.take_over(0.5, 0.5, &mut state), Some(0.5));
+        assert_eq!(function.take_over(0.5, 0.6, &mut state), Some(0.6));
+        // the gui moved the value away from the knob
+        assert_eq!(function.take_over(0.2, 0.62, &mut state), None);
+        assert_eq!(function.take_over(0.2, 0.3, &mut state), None);
+        assert_eq!(function.take_over(0.2, 0.1, &mut state), Some(0.1));
+        // the value sent last, as it comes back from the gui, keeps the pickup
+        assert_eq!(function.take_over(0.1 + 1e-7, 0.4, &mut state), Some(0.4));
+    }
+
+    #[test]
+    fn scale_unscale_round_trip() {
+        let ranges = [(0.0, 1.0), (-1.0, 1.0), (0.0, 24.0)];
+        for curve in [MidiCurve::Linear, MidiCurve::Log, MidiCurve::Exp] {
+            for invert in [false, true] {
+                for deadzone in [0.0, 0.1] {
+                    let scaling = MidiScaling {
+                        invert,
+                        curve,
+                        deadzone,
+                        ..MidiScaling::default()
+                    };
+                    for range in ranges {
+                        for step in 0..=20 {
+                            let value = range.0 + (range.1 - range.0) * step as f32 / 20.0;
+                            let norm_value = scaling.unscale(value, range);
+                            assert!((deadzone - EPSILON..=1.0 - deadzone + EPSILON)
+                                .contains(&norm_value));
+                            let error = (scaling.scale(norm_value, range) - value).abs();
+                            assert!(error < 1e-4 * (range.1 - range.0), "{:?}", scaling);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scale_range_deadzone_steps() {
+        let scaling = MidiScaling {
+            min: Some(-0.5),
+            max: Some(0.5),
+            ..MidiScaling::default()
+        };
+        assert_close(scaling.scale(0.0, (0.0, 10.0)), -0.5);
+        assert_close(scaling.scale(1.0, (0.0, 10.0)), 0.5);
+        let scaling = MidiScaling {
+            deadzone: 0.1,
+            invert: true,
+            ..MidiScaling::default()
+        };
+        assert_close(scaling.scale(0.05, (0.0, 1.0)), 1.0);
+        assert_close(scaling.scale(0.5, (0.0, 1.0)), 0.5);
+        assert_close(scaling.scale(0.95, (0.0, 1.0)), 0.0);
+        let scaling = MidiScaling {
+            steps: 3,
+            ..MidiScaling::default()
+        };
+        assert_close(scaling.scale(0.3, (0.0, 1.0)), 0.5);
+        assert_close(scaling.scale(0.2, (0.0, 1.0)), 0.0);
+        // a quantized value unscales to a position that scales back to it
+        assert_close(
+            scaling.scale(scaling.unscale(0.5, (0.0, 1.0)), (0.0, 1.0)),
+            0.5,
+        );
+    }
+
+    #[test]
+    fn scaling_serializes_only_changed_fields() {
+        assert_eq!(
+            serde_json::to_string(&MidiScaling::default()).unwrap(),
+            "{}"
+        );
+        let scaling = MidiScaling {
+            curve: MidiCurve::Log,
+            encoder: MidiEncoder::TwosComplement,
+            takeover: MidiTakeover::Pickup,
+            ..MidiScaling::default()
+        };
+        let json = serde_json::to_string(&scaling).unwrap();
+        assert_eq!(
+            json,
+            r#"{"curve":"log","encoder":"twos_complement","takeover":"pickup"}"#
+        );
+        assert_eq!(serde_json::from_str::<MidiScaling>(&json).unwrap(), scaling);
+    }
+}
@@ -0,0 +1,525 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::control_change::control_id;
+use crate::jackmidi::MidiMsgAdvanced;
+use crate::midi_functions::{MidiBinding, MidiEncoder, MidiScaling};
+
+/// version written by to_midi_mapping_file
+pub const MIDI_MAPPING_VERSION: u32 = 1;
+
+/// functions the gui reacts on
+pub const MIDI_FUNCTION_NAMES: [&str; 14] = [
+    "intensity_am",
+    "freq_am",
+    "phase_am",
+    "intensity_fm",
+    "freq_fm",
+    "phase_fm",
+    "overdrive_gain",
+    "pan",
+    "pan_spread",
+    "stereo_width",
+    "pitch_bend",
+    "pitch_bend_range",
+    "vibrato_rate",
+    "vibrato_depth",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiControlType {
+    /// 7 bit controller
+    Cc,
+    /// controller 0-31 with its LSB controller + 32
+    Cc14,
+    Note,
+    PitchBend,
+    Rpn,
+    Nrpn,
+}
+
+/// One binding in the readable format, channel 1-16 as shown on devices.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiMappingEntry {
+    pub function: String,
+    #[serde(rename = "type")]
+    pub control_type: MidiControlType,
+    pub channel: u8,
+    /// for cc and cc14
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controller: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<u8>,
+    /// parameter number for rpn and nrpn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<u16>,
+    #[serde(flatten)]
+    pub scaling: MidiScaling,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiMappingFile {
+    pub version: u32,
+    pub mappings: Vec<MidiMappingEntry>,
+}
+
+impl MidiMappingEntry {
+    /// None for messages the readable format has no type for
+    pub fn from_binding(function: &str, binding: &MidiBinding) -> Option<Self> {
+        let channel_of = |id: u16| ((id >> 8) & 0x0f) as u8 + 1;
+        let mut entry = MidiMappingEntry {
+            function: function.to_string(),
+            control_type: MidiControlType::Cc,
+            channel: 1,
+            controller: None,
+            note: None,
+            parameter: None,
+            scaling: binding.scaling,
+        };
+        match binding.msg {
+            MidiMsgAdvanced::MidiControlIdValue(id, _) if id >> 12 == 0xB => {
+                entry.channel = channel_of(id);
+                entry.controller = Some((id & 0x7f) as u8);
+            }
+            MidiMsgAdvanced::MidiControl2IdsValue(id_msb, id_lsb, _)
+                if id_msb == id_lsb && id_msb >> 12 == 0xE =>
+            {
+                entry.control_type = MidiControlType::PitchBend;
+                entry.channel = channel_of(id_msb);
+            }
+            MidiMsgAdvanced::MidiControl2IdsValue(id_msb, _, _) if id_msb >> 12 == 0xB => {
+                entry.control_type = MidiControlType::Cc14;
+                entry.channel = channel_of(id_msb);
+                entry.controller = Some((id_msb & 0x7f) as u8);
+            }
+            MidiMsgAdvanced::MidiNoteOnOff(id_on, _, _, note, _) => {
+                entry.control_type = MidiControlType::Note;
+                entry.channel = channel_of(id_on);
+                entry.note = Some(note);
+            }
+            MidiMsgAdvanced::MidiRpnValue(id, number, _) => {
+                entry.control_type = MidiControlType::Rpn;
+                entry.channel = channel_of(id);
+                entry.parameter = Some(number);
+            }
+            MidiMsgAdvanced::MidiNrpnValue(id, number, _) => {
+                entry.control_type = MidiControlType::Nrpn;
+                entry.channel = channel_of(id);
+                entry.parameter = Some(number);
+            }
+            _ => return None,
+        }
+        Some(entry)
+    }
+
+    pub fn to_binding(&self) -> Result<MidiBinding, String> {
+        validate_function(&self.function)?;
+        validate_scaling(&self.scaling)?;
+        if self.scaling.encoder != MidiEncoder::Absolute && self.control_type != MidiControlType::Cc
+        {
+            return Err(format!("encoder {:?} needs type cc", self.scaling.encoder));
+        }
+        if !(1..=16).contains(&self.channel) {
+            return Err(format!("channel {} is not in 1-16", self.channel));
+        }
+        let channel = self.channel - 1;
+        let msg = match self.control_type {
+            MidiControlType::Cc => {
+                let controller = self.get_field("controller", self.controller, 127)?;
+                MidiMsgAdvanced::MidiControlIdValue(control_id(channel, controller), 0)
+            }
+            MidiControlType::Cc14 => {
+                let controller = self.get_field("controller", self.controller, 31)?;
+                MidiMsgAdvanced::MidiControl2IdsValue(
+                    control_id(channel, controller),
+                    control_id(channel, controller + 32),
+                    0,
+                )
+            }
+            MidiControlType::Note => {
+                let note = self.get_field("note", self.note, 127)?;
+                let id_on = 0x9000 + ((channel as u16) << 8) + note as u16;
+                MidiMsgAdvanced::MidiNoteOnOff(id_on, id_on - 0x1000, true, note, 0)
+            }
+            MidiControlType::PitchBend => {
+                let id = 0xE000 + ((channel as u16) << 8);
+                MidiMsgAdvanced::MidiControl2IdsValue(id, id, 0)
+            }
+            MidiControlType::Rpn => MidiMsgAdvanced::MidiRpnValue(
+                control_id(channel, 0),
+                self.get_field("parameter", self.parameter, 0x3fff)?,
+                0,
+            ),
+            MidiControlType::Nrpn => MidiMsgAdvanced::MidiNrpnValue(
+                control_id(channel, 0),
+                self.get_field("parameter", self.parameter, 0x3fff)?,
+                0,
+            ),
+        };
+        Ok(MidiBinding {
+            msg,
+            scaling: self.scaling,
+        })
+    }
+
+    fn get_field<T: PartialOrd + std::fmt::Display + Copy>(
+        &self,
+        name: &str,
+        value: Option<T>,
+        max: T,
+    ) -> Result<T, String> {
+        let value =
+            value.ok_or_else(|| format!("type {:?} needs a {}", self.control_type, name))?;
+        if value > max {
+            return Err(format!("{} {} is greater than {}", name, value, max));
+        }
+        Ok(value)
+    }
+}
+
+fn validate_function(function: &str) -> Result<(), String> {
+    if MIDI_FUNCTION_NAMES.contains(&function) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown function \"{}\", known functions: {}",
+            function,
+            MIDI_FUNCTION_NAMES.join(", ")
+        ))
+    }
+}
+
+fn validate_scaling(scaling: &MidiScaling) -> Result<(), String> {
+    for (name, value) in [("min", scaling.min), ("max", scaling.max)] {
+        if let Some(value) = value {
+            if !value.is_finite() {
+                return Err(format!("{} {} is not a number", name, value));
+            }
+        }
+    }
+    if !(0.0..0.5).contains(&scaling.deadzone) {
+        return Err(format!("deadzone {} is not in 0.0..0.5", scaling.deadzone));
+    }
+    if !(scaling.sensitivity.is_finite() && scaling.sensitivity > 0.0) {
+        return Err(format!(
+            "sensitivity {} is not greater than 0.0",
+            scaling.sensitivity
+        ));
+    }
+    if !(scaling.acceleration.is_finite() && scaling.acceleration >= 0.0) {
+        return Err(format!(
+            "acceleration {} is not 0.0 or greater",
+            scaling.acceleration
+        ));
+    }
+    Ok(())
+}
+
+/// Versioned readable mapping or the old format with encoded ids
+/// (function name to a list of MidiMsgAdvanced), both validated.
+pub fn parse_midi_mapping(contents: &str) -> Result<HashMap<String, Vec<MidiBinding>>, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(contents).map_err(|err| format!("invalid json: {}", err))?;
+    let mut midi_mapping: HashMap<String, Vec<MidiBinding>> = HashMap::new();
+    if value.get("version").is_some() {
+        let file: MidiMappingFile = serde_json::from_value(value)
+            .map_err(|err| format!("invalid midi mapping: {}", err))?;
+        if file.version > MIDI_MAPPING_VERSION {
+            return Err(format!(
+                "midi mapping version {} is newer than the supported version {}",
+                file.version, MIDI_MAPPING_VERSION
+            ));
+        }
+        for (index, entry) in file.mappings.iter().enumerate() {
+            let binding = entry
+                .to_binding()
+                .map_err(|err| format!("mapping {} ({}): {}", index + 1, entry.function, err))?;
+            midi_mapping
+                .entry(entry.function.clone())
+                .or_default()
+                .push(binding);
+        }
+    } else {
+        midi_mapping = serde_json::from_value(value)
+            .map_err(|err| format!("invalid id based midi mapping: {}", err))?;
+        for (function, bindings) in midi_mapping.iter() {
+            validate_function(function)?;
+            for binding in bindings {
+                validate_scaling(&binding.scaling)
+                    .map_err(|err| format!("{}: {}", function, err))?;
+                if binding.scaling.encoder != MidiEncoder::Absolute
+                    && !matches!(binding.msg, MidiMsgAdvanced::MidiControlIdValue(..))
+                {
+                    return Err(format!(
+                        "{}: encoder {:?} needs a MidiControlIdValue",
+                        function, binding.scaling.encoder
+                    ));
+                }
+            }
+        }
+    }
+    Ok(midi_mapping)
+}
+
+/// readable format, bindings without a readable type are left out
+pub fn to_midi_mapping_file(midi_mapping: &HashMap<String, Vec<MidiBinding>>) -> MidiMappingFile {
+    let mut mappings: Vec<MidiMappingEntry> = midi_mapping
+        .iter()
+        .flat_map(|(function, bindings)| {
+            bindings
+                .iter()
+                .filter_map(|binding| MidiMappingEntry::from_binding(function, binding))
+        })
+        .collect();
+    // stable file contents for version control
+    mappings.sort_by(|a, b| {
+        (&a.function, a.channel, a.controller, a.note, a.parameter)
+            .partial_cmp(&(&b.function, b.channel, b.controller, b.note, b.parameter))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    MidiMappingFile {
+        version: MIDI_MAPPING_VERSION,
+        mappings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_functions::{MidiCurve, MidiTakeover};
+
+    fn mapping_json(entry: &str) -> String {
+        format!(r#"{{"version": 1, "mappings": [{}]}}"#, entry)
+    }
+
+    fn parse_entry(entry: &str) -> Result<MidiBinding, String> {
+        let mut midi_mapping = parse_midi_mapping(&mapping_json(entry))?;
+        assert_eq!(midi_mapping.len(), 1);
+        let (_, mut bindings) = midi_mapping.drain().next().unwrap();
+        assert_eq!(bindings.len(), 1);
+        Ok(bindings.remove(0))
+    }
+
+    fn parse_error(entry: &str) -> String {
+        parse_entry(entry).expect_err(entry)
+    }
+
+    #[test]
+    fn v1_types() {
+        let cases = [
+            (
+                r#"{"function": "freq_am", "type": "cc", "channel": 1, "controller": 22}"#,
+                MidiMsgAdvanced::MidiControlIdValue(0xB016, 0),
+            ),
+            (
+                r#"{"function": "freq_am", "type": "cc14", "channel": 2, "controller": 1}"#,
+                MidiMsgAdvanced::MidiControl2IdsValue(0xB101, 0xB121, 0),
+            ),
+            (
+                r#"{"function": "freq_am", "type": "note", "channel": 10, "note": 36}"#,
+                MidiMsgAdvanced::MidiNoteOnOff(0x9924, 0x8924, true, 36, 0),
+            ),
+            (
+                r#"{"function": "pitch_bend", "type": "pitch_bend", "channel": 16}"#,
+                MidiMsgAdvanced::MidiControl2IdsValue(0xEF00, 0xEF00, 0),
+            ),
+            (
+                r#"{"function": "pitch_bend_range", "type": "rpn", "channel": 1, "parameter": 0}"#,
+                MidiMsgAdvanced::MidiRpnValue(0xB000, 0, 0),
+            ),
+            (
+                r#"{"function": "freq_fm", "type": "nrpn", "channel": 1, "parameter": 1000}"#,
+                MidiMsgAdvanced::MidiNrpnValue(0xB000, 1000, 0),
+            ),
+        ];
+        for (entry, msg) in cases {
+            let binding = parse_entry(entry).unwrap();
+            assert_eq!(binding.msg, msg, "{}", entry);
+            assert_eq!(binding.scaling, MidiScaling::default());
+        }
+    }
+
+    #[test]
+    fn v1_scaling() {
+        let binding = parse_entry(
+            r#"{"function": "pan", "type": "cc", "channel": 1, "controller": 10,
+                "min": -0.5, "max": 0.5, "invert": true, "curve": "exp", "steps": 5,
+                "deadzone": 0.05, "takeover": "scale"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            binding.scaling,
+            MidiScaling {
+                min: Some(-0.5),
+                max: Some(0.5),
+                invert: true,
+                curve: MidiCurve::Exp,
+                steps: 5,
+                deadzone: 0.05,
+                takeover: MidiTakeover::Scale,
+                ..MidiScaling::default()
+            }
+        );
+    }
+
+    #[test]
+    fn legacy_id_based() {
+        // the mapping file of earlier versions
+        let contents = r#"{"overdrive_gain":[{"MidiControlIdValue":[45080,0]}],"phase_am":[{"MidiControlIdValue":[45077,0]}],"freq_am":[{"MidiControlIdValue":[45078,0]}],"intensity_am":[{"MidiControlIdValue":[45076,0]}],"intensity_fm":[{"MidiControlIdValue":[45072,0]}],"freq_fm":[{"MidiControlIdValue":[45073,0]}],"phase_fm":[{"MidiControlIdValue":[45074,0]}]}"#;
+        let midi_mapping = parse_midi_mapping(contents).unwrap();
+        assert_eq!(midi_mapping.len(), 7);
+        assert_eq!(
+            midi_mapping["overdrive_gain"],
+            vec![MidiBinding::new(MidiMsgAdvanced::MidiControlIdValue(
+                45080, 0
+            ))]
+        );
+        let err =
+            parse_midi_mapping(r#"{"volume":[{"MidiControlIdValue":[45080,0]}]}"#).unwrap_err();
+        assert!(err.contains("unknown function \"volume\""), "{}", err);
+        let err = parse_midi_mapping(
+            r#"{"freq_am":[{"msg":{"MidiNoteOnOff":[36900,32804,true,36,0]},"encoder":"binary_offset"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("needs a MidiControlIdValue"), "{}", err);
+    }
+
+    #[test]
+    fn future_version() {
+        let err = parse_midi_mapping(r#"{"version": 2, "mappings": []}"#).unwrap_err();
+        assert!(err.contains("newer"), "{}", err);
+        assert!(parse_midi_mapping(r#"{"version": 1, "mappings": []}"#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(parse_midi_mapping("{").is_err());
+        assert!(parse_midi_mapping(r#"{"version": 1}"#).is_err());
+        assert!(
+            parse_error(r#"{"function": "freq_am", "type": "knob", "channel": 1}"#)
+                .contains("invalid midi mapping")
+        );
+    }
+
+    #[test]
+    fn unknown_function() {
+        let err =
+            parse_error(r#"{"function": "volume", "type": "cc", "channel": 1, "controller": 7}"#);
+        assert!(
+            err.starts_with("mapping 1 (volume): unknown function"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        for (entry, expected) in [
+            (
+                r#"{"function": "pan", "type": "cc", "channel": 0, "controller": 10}"#,
+                "channel 0 is not in 1-16",
+            ),
+            (
+                r#"{"function": "pan", "type": "cc", "channel": 17, "controller": 10}"#,
+                "channel 17 is not in 1-16",
+            ),
+            (
+                r#"{"function": "pan", "type": "cc", "channel": 1, "controller": 128}"#,
+                "controller 128 is greater than 127",
+            ),
+            (
+                r#"{"function": "pan", "type": "cc14", "channel": 1, "controller": 32}"#,
+                "controller 32 is greater than 31",
+            ),
+            (
+                r#"{"function": "pan", "type": "note", "channel": 1, "note": 128}"#,
+                "note 128 is greater than 127",
+            ),
+            (
+                r#"{"function": "pan", "type": "nrpn", "channel": 1, "parameter": 16384}"#,
+                "parameter 16384 is greater than 16383",
+            ),
+        ] {
+            let err = parse_error(entry);
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn missing_field() {
+        for (entry, expected) in [
+            (
+                r#"{"function": "pan", "type": "cc", "channel": 1}"#,
+                "needs a controller",
+            ),
+            (
+                r#"{"function": "pan", "type": "cc14", "channel": 1, "note": 1}"#,
+                "needs a controller",
+            ),
+            (
+                r#"{"function": "pan", "type": "note", "channel": 1}"#,
+                "needs a note",
+            ),
+            (
+                r#"{"function": "pan", "type": "rpn", "channel": 1}"#,
+                "needs a parameter",
+            ),
+        ] {
+            let err = parse_error(entry);
+            assert!(err.contains(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn invalid_scaling() {
+        for (scaling, expected) in [
+            (r#""deadzone": 0.5"#, "deadzone 0.5"),
+            (r#""deadzone": -0.1"#, "deadzone -0.1"),
+            (r#""sensitivity": 0.0"#, "sensitivity 0"),
+            (r#""acceleration": -1.0"#, "acceleration -1"),
+        ] {
+            let err = parse_error(&format!(
+                r#"{{"function": "pan", "type": "cc", "channel": 1, "controller": 10, {}}}"#,
+                scaling
+            ));
+            assert!(err.contains(expected), "{}", err);
+        }
+        let err = parse_error(
+            r#"{"function": "pan", "type": "note", "channel": 1, "note": 1, "encoder": "twos_complement"}"#,
+        );
+        assert!(err.contains("needs type cc"), "{}", err);
+    }
+
+    #[test]
+    fn round_trip() {
+        let contents = mapping_json(
+            r#"{"function": "freq_am", "type": "cc", "channel": 1, "controller": 22, "curve": "log"},
+               {"function": "freq_am", "type": "cc", "channel": 2, "controller": 22, "invert": true},
+               {"function": "pan", "type": "cc", "channel": 1, "controller": 10,
+                "encoder": "sign_magnitude", "sensitivity": 2.0, "acceleration": 0.5},
+               {"function": "phase_am", "type": "cc14", "channel": 3, "controller": 7, "steps": 8},
+               {"function": "phase_fm", "type": "note", "channel": 10, "note": 36, "min": 1.0},
+               {"function": "pitch_bend", "type": "pitch_bend", "channel": 1},
+               {"function": "pitch_bend_range", "type": "rpn", "channel": 1, "parameter": 0},
+               {"function": "vibrato_depth", "type": "nrpn", "channel": 1, "parameter": 300,
+                "takeover": "pickup", "deadzone": 0.1}"#,
+        );
+        let midi_mapping = parse_midi_mapping(&contents).unwrap();
+        let file = to_midi_mapping_file(&midi_mapping);
+        assert_eq!(file.version, MIDI_MAPPING_VERSION);
+        assert_eq!(file.mappings.len(), 8);
+        let written = serde_json::to_string_pretty(&file).unwrap();
+        assert_eq!(parse_midi_mapping(&written).unwrap(), midi_mapping);
+        // written twice gives the same file
+        assert_eq!(
+            serde_json::to_string_pretty(&to_midi_mapping_file(
+                &parse_midi_mapping(&written).unwrap()
+            ))
+            .unwrap(),
+            written
+        );
+    }
+}
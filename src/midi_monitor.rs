@@ -0,0 +1,295 @@
+use crossbeam_channel::Sender;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::jackmidi::{MidiMessage, MidiMsgAdvanced};
+
+/// entries kept by the monitor, older ones are dropped
+pub const MIDI_MONITOR_CAPACITY: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiMonitorKind {
+    Note,
+    ControlChange,
+    ProgramChange,
+    PitchBend,
+    Aftertouch,
+    /// song position, realtime, sysex and unknown messages
+    System,
+}
+
+impl MidiMonitorKind {
+    pub const ALL: [MidiMonitorKind; 6] = [
+        Self::Note,
+        Self::ControlChange,
+        Self::ProgramChange,
+        Self::PitchBend,
+        Self::Aftertouch,
+        Self::System,
+    ];
+
+    pub fn from_midi_message(midi_message: &MidiMessage) -> Self {
+        match midi_message {
+            MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. } => Self::Note,
+            MidiMessage::ControlChange { .. } => Self::ControlChange,
+            MidiMessage::ProgramChange { .. } => Self::ProgramChange,
+            MidiMessage::PitchBend { .. } => Self::PitchBend,
+            MidiMessage::PolyAftertouch { .. } | MidiMessage::ChannelAftertouch { .. } => {
+                Self::Aftertouch
+            }
+            MidiMessage::SongPosition(_)
+            | MidiMessage::SystemRealtime(_)
+            | MidiMessage::SysEx
+            | MidiMessage::Unknown => Self::System,
+        }
+    }
+}
+
+impl std::fmt::Display for MidiMonitorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Note => "note",
+            Self::ControlChange => "cc",
+            Self::ProgramChange => "program",
+            Self::PitchBend => "pitch bend",
+            Self::Aftertouch => "aftertouch",
+            Self::System => "system",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One incoming message, decoded in the midi thread.
+#[derive(Clone, Debug)]
+pub struct MidiMonitorEntry {
+    pub received: Instant,
+    /// 0..=15, None for system messages
+    pub channel: Option<u8>,
+    pub kind: MidiMonitorKind,
+    /// Display of the message struct
+    pub message: String,
+    /// Display of the MidiMsgAdvanced, the message as it is written in the mapping file
+    pub advanced: Option<String>,
+}
+
+impl MidiMonitorEntry {
+    pub fn new(
+        midi_message: &MidiMessage,
+        bytes: &[u8],
+        time: u64,
+        midi_advanced_msg: Option<&MidiMsgAdvanced>,
+    ) -> Self {
+        MidiMonitorEntry {
+            received: Instant::now(),
+            channel: midi_message.get_channel(),
+            kind: MidiMonitorKind::from_midi_message(midi_message),
+            message: midi_message.to_msg_base(bytes, time).to_string(),
+            advanced: midi_advanced_msg.map(|midi_advanced_msg| midi_advanced_msg.to_string()),
+        }
+    }
+}
+
+/// Midi thread side of the monitor, entries are only built while the monitor receives them.
+pub struct MidiMonitorTap {
+    pub tx_entry: Sender<MidiMonitorEntry>,
+    pub receiving: Arc<AtomicBool>,
+}
+
+impl MidiMonitorTap {
+    /// the channel should be bounded, entries are dropped when the gui does not keep up
+    pub fn send(
+        &self,
+        midi_message: &MidiMessage,
+        bytes: &[u8],
+        time: u64,
+        midi_advanced_msg: Option<&MidiMsgAdvanced>,
+    ) {
+        if !self.receiving.load(Ordering::Relaxed) {
+            return;
+        }
+        let _ = self.tx_entry.try_send(MidiMonitorEntry::new(
+            midi_message,
+            bytes,
+            time,
+            midi_advanced_msg,
+        ));
+    }
+}
+
+/// Last entries with pause and channel and type filters.
+pub struct MidiMonitor {
+    entries: VecDeque<MidiMonitorEntry>,
+    start: Instant,
+    /// shared with the tap, false while the window is closed or paused
+    receiving: Arc<AtomicBool>,
+    pub paused: bool,
+    /// None shows all channels
+    pub channel: Option<u8>,
+    /// shown kinds in the order of MidiMonitorKind::ALL
+    pub kinds: [bool; 6],
+}
+
+impl Default for MidiMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiMonitor {
+    pub fn new() -> Self {
+        MidiMonitor {
+            entries: VecDeque::with_capacity(MIDI_MONITOR_CAPACITY),
+            start: Instant::now(),
+            receiving: Arc::new(AtomicBool::new(false)),
+            paused: false,
+            channel: None,
+            kinds: [true; 6],
+        }
+    }
+
+    /// tap for the midi thread that sends while the monitor is receiving
+    pub fn tap(&self, tx_entry: Sender<MidiMonitorEntry>) -> MidiMonitorTap {
+        MidiMonitorTap {
+            tx_entry,
+            receiving: self.receiving.clone(),
+        }
+    }
+
+    /// the midi thread builds entries only while the window is open and not paused
+    pub fn set_open(&self, open: bool) {
+        self.receiving
+            .store(open && !self.paused, Ordering::Relaxed);
+    }
+
+    pub fn is_receiving(&self) -> bool {
+        self.receiving.load(Ordering::Relaxed)
+    }
+
+    /// dropped while paused
+    pub fn push(&mut self, entry: MidiMonitorEntry) {
+        if self.paused {
+            return;
+        }
+        if self.entries.len() >= MIDI_MONITOR_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// seconds since the monitor was created
+    pub fn get_timestamp(&self, entry: &MidiMonitorEntry) -> f64 {
+        entry
+            .received
+            .saturating_duration_since(self.start)
+            .as_secs_f64()
+    }
+
+    pub fn is_shown(&self, entry: &MidiMonitorEntry) -> bool {
+        let kind_shown = MidiMonitorKind::ALL
+            .iter()
+            .zip(self.kinds.iter())
+            .any(|(kind, shown)| *shown && *kind == entry.kind);
+        let channel_shown = match (self.channel, entry.channel) {
+            (Some(channel), Some(entry_channel)) => channel == entry_channel,
+            _ => true,
+        };
+        kind_shown && channel_shown
+    }
+
+    /// entries passing the filters, oldest first
+    pub fn filtered(&self) -> impl Iterator<Item = &MidiMonitorEntry> {
+        self.entries.iter().filter(|entry| self.is_shown(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::bounded;
+
+    fn entry(bytes: &[u8]) -> MidiMonitorEntry {
+        MidiMonitorEntry::new(&MidiMessage::from_bytes(bytes), bytes, 0, None)
+    }
+
+    #[test]
+    fn kind_and_channel_filters() {
+        let mut monitor = MidiMonitor::new();
+        monitor.push(entry(&[0x90, 60, 100]));
+        monitor.push(entry(&[0xB1, 7, 100]));
+        monitor.push(entry(&[0xE1, 0, 64]));
+        monitor.push(entry(&[0xF8]));
+        assert_eq!(monitor.filtered().count(), 4);
+
+        monitor.channel = Some(1);
+        // system messages have no channel and pass the channel filter
+        let kinds: Vec<MidiMonitorKind> = monitor.filtered().map(|entry| entry.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                MidiMonitorKind::ControlChange,
+                MidiMonitorKind::PitchBend,
+                MidiMonitorKind::System
+            ]
+        );
+
+        monitor.kinds = [true, false, true, true, true, false];
+        let kinds: Vec<MidiMonitorKind> = monitor.filtered().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, vec![MidiMonitorKind::PitchBend]);
+        // filters hide entries, they stay in the monitor
+        assert_eq!(monitor.len(), 4);
+    }
+
+    #[test]
+    fn push_paused_and_capacity() {
+        let mut monitor = MidiMonitor::new();
+        monitor.paused = true;
+        monitor.push(entry(&[0x90, 60, 100]));
+        assert!(monitor.is_empty());
+        monitor.paused = false;
+        for value in 0..MIDI_MONITOR_CAPACITY + 10 {
+            monitor.push(entry(&[0xB0, 1, (value % 128) as u8]));
+        }
+        assert_eq!(monitor.len(), MIDI_MONITOR_CAPACITY);
+        // the oldest entries were dropped
+        let first = monitor.filtered().next().unwrap();
+        assert!(first.message.contains(&format!("value: {}", 10)));
+        monitor.clear();
+        assert!(monitor.is_empty());
+    }
+
+    #[test]
+    fn tap_sends_only_while_receiving() {
+        let mut monitor = MidiMonitor::new();
+        let (tx_entry, rx_entry) = bounded(2);
+        let tap = monitor.tap(tx_entry);
+        let midi_message = MidiMessage::from_bytes(&[0xF8]);
+        tap.send(&midi_message, &[0xF8], 0, None);
+        assert!(rx_entry.try_recv().is_err());
+
+        monitor.set_open(true);
+        for _ in 0..5 {
+            tap.send(&midi_message, &[0xF8], 0, None);
+        }
+        // a full channel drops entries instead of growing
+        assert_eq!(rx_entry.try_iter().count(), 2);
+
+        monitor.paused = true;
+        monitor.set_open(true);
+        tap.send(&midi_message, &[0xF8], 0, None);
+        assert!(rx_entry.try_recv().is_err());
+    }
+}
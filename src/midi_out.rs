@@ -0,0 +1,175 @@
+use crate::jackmidi::{MidiMsgAdvanced, MidiMsgGeneric, SystemRealtime};
+use crate::util::{status_byte, u14_to_msb_lsb};
+
+/// midi clock pulses per quarter note
+pub const CLOCK_PPQN: f64 = 24.0;
+/// events per period that fit without reallocation in the real-time thread
+const MIDI_OUT_CAPACITY: usize = 256;
+
+/// Outgoing events of one period sorted by frame offset, as the jack midi port requires.
+pub struct MidiOutBuffer {
+    events: Vec<MidiMsgGeneric>,
+}
+
+impl Default for MidiOutBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiOutBuffer {
+    pub fn new() -> Self {
+        MidiOutBuffer {
+            events: Vec::with_capacity(MIDI_OUT_CAPACITY),
+        }
+    }
+
+    /// events at the same offset keep their order, events beyond the capacity are dropped
+    pub fn push(&mut self, frame_offset: u32, bytes: &[u8]) {
+        if self.events.len() >= MIDI_OUT_CAPACITY {
+            return;
+        }
+        let index = self
+            .events
+            .partition_point(|event| event.time <= frame_offset as u64);
+        self.events.insert(
+            index,
+            MidiMsgGeneric::from_bytes(bytes, frame_offset as u64),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// hand out all events in order and clear the buffer
+    pub fn drain(&mut self, mut write: impl FnMut(u32, &[u8])) {
+        for event in self.events.drain(..) {
+            write(event.time as u32, event.get_bytes());
+        }
+    }
+}
+
+/// Midi clock master: 24 clock pulses per quarter note, start and stop messages.
+pub struct MidiClock {
+    sample_rate: f64,
+    bpm: f64,
+    running: bool,
+    /// frames until the next clock pulse
+    frames_to_pulse: f64,
+    pending: Option<SystemRealtime>,
+}
+
+impl MidiClock {
+    pub fn new(sample_rate: f64, bpm: f64) -> Self {
+        MidiClock {
+            sample_rate,
+            bpm: bpm.max(1.0),
+            running: false,
+            frames_to_pulse: 0.0,
+            pending: None,
+        }
+    }
+
+    pub fn get_bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    pub fn set_bpm(&mut self, bpm: f64) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// sends start and pulses from the next period on
+    pub fn start(&mut self) {
+        self.running = true;
+        self.frames_to_pulse = 0.0;
+        self.pending = Some(SystemRealtime::Start);
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.pending = Some(SystemRealtime::Stop);
+    }
+
+    /// start, stop and clock pulses of the next num_frames frames
+    pub fn process(&mut self, num_frames: usize, midi_out: &mut MidiOutBuffer) {
+        if let Some(kind) = self.pending.take() {
+            midi_out.push(0, &[kind.to_status_byte()]);
+        }
+        if !self.running {
+            return;
+        }
+        let frames_per_pulse = self.sample_rate * 60.0 / (self.bpm * CLOCK_PPQN);
+        while self.frames_to_pulse < num_frames as f64 {
+            midi_out.push(
+                self.frames_to_pulse as u32,
+                &[SystemRealtime::Clock.to_status_byte()],
+            );
+            self.frames_to_pulse += frames_per_pulse;
+        }
+        self.frames_to_pulse -= num_frames as f64;
+    }
+}
+
+/// Messages that set a controller to value (0.0..=1.0), e.g. to move a motorized fader or LED ring
+/// to a parameter changed in the gui.
+pub fn control_feedback(midi_advanced_msg: &MidiMsgAdvanced, value: f32) -> Vec<MidiMsgGeneric> {
+    let value = value.clamp(0.0, 1.0);
+    let value7 = (value * 127.0).round() as u8;
+    let value14 = (value * 16383.0).round() as u16;
+    let (msb, lsb) = u14_to_msb_lsb(value14);
+    // ids are 0xS000 + (channel << 8) + data byte
+    let status = |id: u16| (id >> 8) as u8;
+    let data = |id: u16| (id & 0x7f) as u8;
+    let msg = |bytes: &[u8]| MidiMsgGeneric::from_bytes(bytes, 0);
+    match *midi_advanced_msg {
+        MidiMsgAdvanced::MidiEmpty => Vec::new(),
+        MidiMsgAdvanced::MidiNoteOnOff(id, _, _, _, _) => {
+            vec![msg(&[status(id), data(id), value7])]
+        }
+        MidiMsgAdvanced::MidiControlIdValue(id, _) => vec![msg(&[status(id), data(id), value7])],
+        // pitch bend is mapped with the same id twice
+        MidiMsgAdvanced::MidiControl2IdsValue(id_msb, id_lsb, _) if id_msb == id_lsb => {
+            vec![msg(&[status(id_msb), lsb, msb])]
+        }
+        MidiMsgAdvanced::MidiControl2IdsValue(id_msb, id_lsb, _) => vec![
+            msg(&[status(id_msb), data(id_msb), msb]),
+            msg(&[status(id_lsb), data(id_lsb), lsb]),
+        ],
+        MidiMsgAdvanced::MidiRpnValue(id, number, _) => {
+            parameter_feedback(status(id), (101, 100), number, (msb, lsb))
+        }
+        MidiMsgAdvanced::MidiNrpnValue(id, number, _) => {
+            parameter_feedback(status(id), (99, 98), number, (msb, lsb))
+        }
+    }
+}
+
+/// parameter number selection followed by data entry MSB and LSB
+fn parameter_feedback(
+    status: u8,
+    (control_msb, control_lsb): (u8, u8),
+    number: u16,
+    (value_msb, value_lsb): (u8, u8),
+) -> Vec<MidiMsgGeneric> {
+    let (number_msb, number_lsb) = u14_to_msb_lsb(number);
+    let channel = status & 0x0f;
+    let status = status_byte(0x0b, channel);
+    [
+        [status, control_msb, number_msb],
+        [status, control_lsb, number_lsb],
+        [status, 6, value_msb],
+        [status, 38, value_lsb],
+    ]
+    .iter()
+    .map(|bytes| MidiMsgGeneric::from_bytes(bytes, 0))
+    .collect()
+}
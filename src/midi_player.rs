@@ -0,0 +1,195 @@
+use bus::BusReader;
+use crossbeam_channel::Sender;
+use std::time::{Duration, Instant};
+
+use crate::control_change::ControlChangeDecoder;
+use crate::engine::{decode_engine_event, EventTarget, ScheduledEvent};
+use crate::jackmidi::MidiMsgGeneric;
+use crate::smf::{Division, Smf, SmfEventKind};
+
+const DEFAULT_TEMPO: u32 = 500_000;
+
+#[derive(Copy, Clone, Debug)]
+pub struct TimedMidiMsg {
+    /// seconds from the start of the file
+    pub time: f64,
+    pub msg: MidiMsgGeneric,
+}
+
+/// Channel messages of all tracks merged into one timeline, ticks converted with the tempo map
+pub struct MidiPlayer {
+    events: Vec<TimedMidiMsg>,
+    duration: f64,
+}
+
+impl MidiPlayer {
+    pub fn from_smf(smf: &Smf) -> Self {
+        // merge the tracks, events at the same tick keep the track order
+        let mut merged: Vec<(u64, &SmfEventKind)> = smf
+            .tracks
+            .iter()
+            .flat_map(|track| track.iter().map(|event| (event.tick, &event.kind)))
+            .collect();
+        merged.sort_by_key(|(tick, _)| *tick);
+
+        let mut events = Vec::new();
+        let mut tempo = DEFAULT_TEMPO;
+        let mut last_tick: u64 = 0;
+        let mut last_time: f64 = 0.0;
+        for (tick, kind) in merged {
+            let delta_ticks = (tick - last_tick) as f64;
+            let time = last_time
+                + match smf.division {
+                    Division::TicksPerQuarter(ticks_per_quarter) => {
+                        delta_ticks * tempo as f64 / 1_000_000.0 / ticks_per_quarter.max(1) as f64
+                    }
+                    Division::Smpte {
+                        frames_per_second,
+                        ticks_per_frame,
+                    } => {
+                        let fps = match frames_per_second {
+                            29 => 29.97,
+                            fps => fps as f64,
+                        };
+                        delta_ticks / (fps * ticks_per_frame.max(1) as f64)
+                    }
+                };
+            last_tick = tick;
+            last_time = time;
+            match kind {
+                SmfEventKind::Tempo(new_tempo) => tempo = *new_tempo,
+                SmfEventKind::Midi { len, data } => events.push(TimedMidiMsg {
+                    time,
+                    msg: MidiMsgGeneric::from_bytes(&data[..*len], (time * 1_000_000.0) as u64),
+                }),
+                _ => (),
+            }
+        }
+        MidiPlayer {
+            events,
+            duration: last_time,
+        }
+    }
+
+    pub fn get_events(&self) -> &[TimedMidiMsg] {
+        &self.events
+    }
+
+    /// length in seconds up to the last event of all tracks
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// note on/off, pitch bend and its range, pedals and modulation as engine events
+    /// for offline rendering, like the engine schedules live midi
+    pub fn to_scheduled_events(&self, sample_rate: f32, note_length: usize) -> Vec<ScheduledEvent> {
+        let mut control_change_decoder = ControlChangeDecoder::new();
+        self.events
+            .iter()
+            .filter_map(|timed_msg| {
+                let bytes = timed_msg.msg.get_bytes();
+                let event = decode_engine_event(bytes, note_length, &mut control_change_decoder)?;
+                Some(ScheduledEvent {
+                    frame: (timed_msg.time * sample_rate as f64).round() as u64,
+                    target: EventTarget::from_midi_bytes(bytes),
+                    event,
+                })
+            })
+            .collect()
+    }
+
+    /// real time playback into the jack process callback, like an external keyboard
+    pub fn play(
+        self,
+        tx_midi: Sender<MidiMsgGeneric>,
+        mut rx_close: BusReader<bool>,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            'events: for timed_msg in self.events {
+                let due = Duration::from_secs_f64(timed_msg.time);
+                loop {
+                    if let Ok(false) = rx_close.try_recv() {
+                        break 'events;
+                    }
+                    let elapsed = start.elapsed();
+                    if elapsed >= due {
+                        break;
+                    }
+                    std::thread::sleep((due - elapsed).min(Duration::from_millis(10)));
+                }
+                if tx_midi.send(timed_msg.msg).is_err() {
+                    break;
+                }
+            }
+            println!("exit midi player thread\n");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineEvent;
+
+    fn midi_player(messages: &[(f64, &[u8])]) -> MidiPlayer {
+        MidiPlayer {
+            events: messages
+                .iter()
+                .map(|(time, bytes)| TimedMidiMsg {
+                    time: *time,
+                    msg: MidiMsgGeneric::from_bytes(bytes, (*time * 1_000_000.0) as u64),
+                })
+                .collect(),
+            duration: messages.last().map_or(0.0, |(time, _)| *time),
+        }
+    }
+
+    #[test]
+    fn rpn_0_sets_the_pitch_bend_range() {
+        // 12 semitones and 50 cents on channel 2
+        let player = midi_player(&[
+            (0.0, &[0xB1, 101, 0]),
+            (0.0, &[0xB1, 100, 0]),
+            (0.5, &[0xB1, 6, 12]),
+            (1.0, &[0xB1, 38, 50]),
+            (1.0, &[0xE1, 0x00, 0x60]),
+        ]);
+        let events = player.to_scheduled_events(1000.0, 100);
+        let ranges: Vec<(u64, EventTarget, f32)> = events
+            .iter()
+            .filter_map(|scheduled| match scheduled.event {
+                EngineEvent::PitchBendRange(pitch_bend_range) => {
+                    Some((scheduled.frame, scheduled.target, pitch_bend_range))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (500, EventTarget::Channel(1), 12.0),
+                (1000, EventTarget::Channel(1), 12.5)
+            ]
+        );
+        assert!(matches!(
+            events.last().map(|scheduled| &scheduled.event),
+            Some(EngineEvent::PitchBend(_))
+        ));
+    }
+
+    #[test]
+    fn other_parameters_keep_the_range() {
+        // RPN 1 (fine tuning) and NRPN data entry
+        let player = midi_player(&[
+            (0.0, &[0xB0, 101, 0]),
+            (0.0, &[0xB0, 100, 1]),
+            (0.0, &[0xB0, 6, 64]),
+            (0.0, &[0xB0, 99, 0]),
+            (0.0, &[0xB0, 98, 0]),
+            (0.0, &[0xB0, 6, 24]),
+        ]);
+        let events = player.to_scheduled_events(1000.0, 100);
+        assert!(events.is_empty());
+    }
+}
@@ -1,37 +1,207 @@
 use crate::{
-    jackmidi::{MidiMsgAdvanced, MidiMsgBase, MidiMsgGeneric},
+    bound_notes::BoundNotes,
+    control_change::ControlChangeDecoder,
+    jackmidi::{MidiDecoder, MidiMsgAdvanced, MidiMsgGeneric},
+    midi_functions::{MidiFunction, MidiTakeoverState},
+    midi_monitor::MidiMonitorTap,
     trigger_note_msg::{NoteType, TriggerNoteMsg},
     util::*,
 };
 use bus::BusReader;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
 use std::collections::HashMap;
+use std::time::Duration;
+
+/// longest time gui commands wait while no midi arrives
+const LEARN_CMD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum MidiLearnCmd {
+    /// bind the next controller message to this function
+    Learn(String),
+    Cancel,
+    /// remove all bindings of this function
+    Forget(String),
+    /// value of a function changed in the gui, relative encoders continue from it
+    Value(String, f32),
+    /// replace the whole mapping, e.g. after the mapping file changed
+    Mapping(HashMap<MidiMsgAdvanced, Vec<MidiFunction>>),
+}
+
+/// commands from the gui and the learned bindings sent back
+pub struct MidiLearn {
+    pub rx_cmd: Receiver<MidiLearnCmd>,
+    pub tx_learned: Sender<(String, MidiMsgAdvanced)>,
+    /// follows the note bindings of the mapping changed by the commands
+    pub bound_notes: BoundNotes,
+}
+
+fn forget_midi_function(
+    midi_advanced_msgs2midi_functions: &mut HashMap<MidiMsgAdvanced, Vec<MidiFunction>>,
+    function: &str,
+) {
+    for functions in midi_advanced_msgs2midi_functions.values_mut() {
+        functions.retain(|mapped_function| mapped_function.get_name() != function);
+    }
+    midi_advanced_msgs2midi_functions.retain(|_, functions| !functions.is_empty());
+}
+
+/// functions every controller has without an entry in the mapping file
+fn builtin_midi_function(midi_advanced_msg: &MidiMsgAdvanced) -> Option<(&'static str, f32)> {
+    match *midi_advanced_msg {
+        MidiMsgAdvanced::MidiControl2IdsValue(id0, id1, value)
+            if id0 == id1 && id0 >> 12 == 0xE =>
+        {
+            Some(("pitch_bend", pitch_bend_from_u14(value)))
+        }
+        _ => None,
+    }
+}
+
+/// value of a function for a controller message, None while the controller has not taken over
+fn controller_function_value(
+    function: &MidiFunction,
+    midi_advanced_msg: &MidiMsgAdvanced,
+    last_value: Option<f32>,
+    takeover_state: &mut MidiTakeoverState,
+) -> Option<f32> {
+    let norm_value = midi_advanced_msg.get_norm_value();
+    match last_value {
+        // unknown values start in the middle of the range and can not be taken over
+        None => Some(
+            function
+                .scale_relative(function.scale(0.5), midi_advanced_msg)
+                .unwrap_or_else(|| function.scale(norm_value)),
+        ),
+        Some(last_value) => match function.scale_relative(last_value, midi_advanced_msg) {
+            Some(value) => Some(value),
+            None => function.take_over(last_value, norm_value, takeover_state),
+        },
+    }
+}
 
 pub fn midi_process_fct(
     midi_receiver: Receiver<MidiMsgGeneric>,
     tx_note_velocity: Sender<TriggerNoteMsg>,
-    tx_trigger: Sender<TriggerNoteMsg>,
     mut rx1_close: BusReader<bool>,
     tx_midi_ctrl: Option<Sender<(String, f32)>>,
-    midi_advanced_msgs2midi_functions: Option<HashMap<MidiMsgAdvanced, Vec<String>>>,
+    midi_advanced_msgs2midi_functions: Option<HashMap<MidiMsgAdvanced, Vec<MidiFunction>>>,
+    midi_learn: Option<MidiLearn>,
+    midi_monitor_tap: Option<MidiMonitorTap>,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
-        let mut last_midi_msg: Option<Box<dyn MidiMsgBase>> = None;
+        let mut control_change_decoder = midi_advanced_msgs2midi_functions.as_ref().map_or_else(
+            ControlChangeDecoder::new,
+            ControlChangeDecoder::from_midi_mapping,
+        );
+        let mut midi_decoder = MidiDecoder::new();
+        let mut midi_advanced_msgs2midi_functions = midi_advanced_msgs2midi_functions;
+        let mut learn_function: Option<String> = None;
+        // last value of each function, the start of relative encoder moves
+        let mut function_values: HashMap<String, f32> = HashMap::new();
+        let mut takeover_states: HashMap<(MidiMsgAdvanced, String), MidiTakeoverState> =
+            HashMap::new();
         let mut run_loop = true;
 
         while run_loop {
-            while let Ok(msg_generic) = midi_receiver.recv() {
-                let midi_msg: Box<dyn MidiMsgBase> = msg_generic.into();
+            loop {
+                let received = midi_receiver.recv_timeout(LEARN_CMD_POLL_INTERVAL);
+                // mapping changes apply from the next message on, before it is decoded,
+                // values from the gui are taken without midi input so they do not pile up
+                if let Some(ref midi_learn) = midi_learn {
+                    for cmd in midi_learn.rx_cmd.try_iter() {
+                        match cmd {
+                            MidiLearnCmd::Learn(function) => learn_function = Some(function),
+                            MidiLearnCmd::Cancel => learn_function = None,
+                            MidiLearnCmd::Forget(function) => {
+                                if let Some(ref mut midi_advanced_msgs2midi_functions) =
+                                    midi_advanced_msgs2midi_functions
+                                {
+                                    forget_midi_function(
+                                        midi_advanced_msgs2midi_functions,
+                                        &function,
+                                    );
+                                    midi_learn
+                                        .bound_notes
+                                        .set_midi_mapping(midi_advanced_msgs2midi_functions);
+                                }
+                            }
+                            MidiLearnCmd::Value(function, value) => {
+                                function_values.insert(function, value);
+                            }
+                            MidiLearnCmd::Mapping(mapping) => {
+                                control_change_decoder =
+                                    ControlChangeDecoder::from_midi_mapping(&mapping);
+                                midi_learn.bound_notes.set_midi_mapping(&mapping);
+                                midi_advanced_msgs2midi_functions = Some(mapping);
+                                takeover_states.clear();
+                            }
+                        }
+                    }
+                }
+                let msg_generic = match received {
+                    Ok(msg_generic) => msg_generic,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let midi_message = match midi_decoder.decode(msg_generic.get_bytes()) {
+                    Some(midi_message) => midi_message,
+                    None => continue,
+                };
 
-                let midi_advanced_msg = MidiMsgAdvanced::from_current_and_last_opt_midi_msgs((
-                    midi_msg,
-                    &mut last_midi_msg,
-                ));
+                let midi_advanced_msg =
+                    MidiMsgAdvanced::from_midi_message(midi_message, &mut control_change_decoder);
+                if let Some(ref midi_monitor_tap) = midi_monitor_tap {
+                    midi_monitor_tap.send(
+                        &midi_message,
+                        msg_generic.get_bytes(),
+                        msg_generic.time,
+                        midi_advanced_msg.as_ref(),
+                    );
+                }
+                if let Some(ref midi_learn) = midi_learn {
+                    // notes play, every other message is bound to the learned function
+                    if let Some(ref midi_advanced_msg) = midi_advanced_msg {
+                        if !matches!(midi_advanced_msg, MidiMsgAdvanced::MidiNoteOnOff(..)) {
+                            if let Some(function) = learn_function.take() {
+                                let mut learned_msg = midi_advanced_msg.clone();
+                                learned_msg.reset_value();
+                                let midi_advanced_msgs2midi_functions =
+                                    midi_advanced_msgs2midi_functions
+                                        .get_or_insert_with(HashMap::new);
+                                forget_midi_function(midi_advanced_msgs2midi_functions, &function);
+                                midi_advanced_msgs2midi_functions
+                                    .entry(learned_msg.clone())
+                                    .or_default()
+                                    .push(MidiFunction::new(function.clone()));
+                                let _ = midi_learn.tx_learned.send((function, learned_msg));
+                            }
+                        }
+                    }
+                }
                 if let Some(midi_advanced_msg) = midi_advanced_msg {
                     let _id = midi_advanced_msg.get_id();
                     match midi_advanced_msg {
-                        MidiMsgAdvanced::MidiNoteOnOff(_id0, _id1, bvalue, note, intensity) => {
-                            if bvalue {
+                        MidiMsgAdvanced::MidiNoteOnOff(id0, id1, bvalue, note, intensity) => {
+                            // pads and buttons bound to a function by their note on,
+                            // the engine does not play them
+                            let functions = midi_advanced_msgs2midi_functions.as_ref().and_then(
+                                |midi_advanced_msgs2midi_functions| {
+                                    midi_advanced_msgs2midi_functions.get(
+                                        &MidiMsgAdvanced::MidiNoteOnOff(id0, id1, true, note, 0),
+                                    )
+                                },
+                            );
+                            if let Some(functions) = functions {
+                                if let (true, Some(tx_midi_ctrl)) = (bvalue, tx_midi_ctrl.as_ref())
+                                {
+                                    for function in functions {
+                                        let _ = tx_midi_ctrl.try_send((
+                                            function.get_name(),
+                                            function.scale(intensity as f32 / 127.0),
+                                        ));
+                                    }
+                                }
+                            } else if bvalue {
                                 let velocity = intensity as f32 / 127.0;
                                 let note_on_msg = TriggerNoteMsg {
                                     note_type: NoteType::NoteOn,
@@ -40,7 +210,6 @@ pub fn midi_process_fct(
                                     length: 96000,
                                 };
                                 tx_note_velocity.send(note_on_msg).unwrap();
-                                tx_trigger.send(note_on_msg).unwrap();
                             } else {
                                 let velocity = intensity as f32 / 127.0;
                                 let note_off_msg = TriggerNoteMsg {
@@ -49,23 +218,42 @@ pub fn midi_process_fct(
                                     velocity,
                                     length: 96000,
                                 };
-                                tx_note_velocity.send(note_off_msg.clone()).unwrap();
-                                tx_trigger.send(note_off_msg).unwrap();
+                                tx_note_velocity.send(note_off_msg).unwrap();
                             }
                         }
                         mut other_midi_advanced_msg => {
                             if let Some(ref tx_midi_ctrl) = tx_midi_ctrl {
+                                if let Some((function, value)) =
+                                    builtin_midi_function(&other_midi_advanced_msg)
+                                {
+                                    let _ = tx_midi_ctrl.try_send((function.to_string(), value));
+                                }
                                 if let Some(ref midi_advanced_msgs2midi_functions) =
                                     midi_advanced_msgs2midi_functions
                                 {
-                                    let value = other_midi_advanced_msg.get_norm_value();
+                                    let msg_with_value = other_midi_advanced_msg.clone();
                                     other_midi_advanced_msg.reset_value();
                                     if let Some(functions) = midi_advanced_msgs2midi_functions
                                         .get(&other_midi_advanced_msg)
                                     {
+                                        // values in the range of the function
                                         for function in functions {
-                                            let _ = tx_midi_ctrl
-                                                .try_send((function.to_string(), value));
+                                            let name = function.get_name();
+                                            let Some(value) = controller_function_value(
+                                                function,
+                                                &msg_with_value,
+                                                function_values.get(&name).copied(),
+                                                takeover_states
+                                                    .entry((
+                                                        other_midi_advanced_msg.clone(),
+                                                        name.clone(),
+                                                    ))
+                                                    .or_default(),
+                                            ) else {
+                                                continue;
+                                            };
+                                            function_values.insert(name.clone(), value);
+                                            let _ = tx_midi_ctrl.try_send((name, value));
                                         }
                                     }
                                 }
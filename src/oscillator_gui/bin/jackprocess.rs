@@ -3,18 +3,100 @@ extern crate wmidi;
 use bus::BusReader;
 use crossbeam_channel::{Receiver, Sender};
 use oscillator_lib::{
-    adsr::Adsr, ctrl_msg::CtrlMsg, effect::Effect, jackmidi::MidiMsgGeneric, overdrive::Overdrive,
-    tone_handling::ToneHandling, trigger_note_msg::TriggerNoteMsg,
+    bound_notes::BoundNotes,
+    engine::{Engine, EngineEvent, EventTarget},
+    jackmidi::{MidiMessage, MidiMsgGeneric},
+    midi_out::{MidiClock, MidiOutBuffer},
+    part::{MidiChannel, PartConfig},
+    recorder::RecorderTap,
+    scope::ScopeTap,
+    trigger_note_msg::{NoteType, TriggerNoteMsg},
+    util::{freq_to_note, status_byte},
 };
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::{process::exit, thread, time::Duration};
+
+const NOTE_LENGTH: usize = 96000;
+/// maximum number of queued triggers handled in one period
+pub const DEFAULT_TRIGGER_BUDGET: usize = 64;
+/// parts of the engine, each with its own output ports
+pub const NUM_PARTS: usize = 4;
+
+/// triggers from the gui for a part, drained up to budget per period
+pub struct TriggerQueue {
+    pub rx_trigger: Receiver<(usize, TriggerNoteMsg)>,
+    pub budget: usize,
+    pub backlog: Arc<AtomicUsize>,
+}
+
+/// copies of the main output for the gui
+pub struct OutputTaps {
+    pub recorder_tap: RecorderTap,
+    pub scope_tap: ScopeTap,
+}
+
+/// midi into the engine and a copy for the midi thread
+pub struct MidiInput {
+    pub midi_sender: Sender<MidiMsgGeneric>,
+    /// midi from the file player
+    pub rx_midi_player: Receiver<MidiMsgGeneric>,
+    /// notes the engine does not play, they are bound to a function
+    pub bound_notes: BoundNotes,
+}
+
+/// what goes to the midi out port besides the messages from rx_midi_out
+pub struct MidiOutput {
+    /// e.g. controller feedback from the gui, sent at the start of the next period
+    pub rx_midi_out: Receiver<MidiMsgGeneric>,
+    /// echo the notes the engine plays
+    pub echo: bool,
+    /// act as clock master with this tempo
+    pub clock_bpm: Option<f64>,
+}
+
+/// note on/off bytes of a trigger on the channel of the part
+fn trigger_to_midi_bytes(trigger_msg: &TriggerNoteMsg, channel: MidiChannel) -> [u8; 3] {
+    let channel = match channel {
+        MidiChannel::Channel(channel) => channel,
+        MidiChannel::Omni | MidiChannel::Off => 0,
+    };
+    let status = match trigger_msg.note_type {
+        NoteType::NoteOn => 0x09,
+        NoteType::NoteOff => 0x08,
+    };
+    [
+        status_byte(status, channel),
+        freq_to_note(trigger_msg.freq),
+        (trigger_msg.velocity.clamp(0.0, 1.0) * 127.0).round() as u8,
+    ]
+}
+
+fn is_note(bytes: &[u8]) -> bool {
+    matches!(
+        MidiMessage::from_bytes(bytes),
+        MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. }
+    )
+}
+
 pub fn start_jack_thread(
     mut rx_close: BusReader<bool>,
-    rx_ctrl: Receiver<CtrlMsg>,
-    rx_adsr: Receiver<Adsr>,
-    rx_trigger: Receiver<TriggerNoteMsg>,
-    midi_sender: Sender<MidiMsgGeneric>,
+    rx_part_event: Receiver<(usize, EngineEvent)>,
+    trigger_queue: TriggerQueue,
+    midi_input: MidiInput,
+    output_taps: OutputTaps,
+    midi_output: MidiOutput,
 ) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
+        let OutputTaps {
+            recorder_tap,
+            scope_tap,
+        } = output_taps;
+        let MidiInput {
+            midi_sender,
+            rx_midi_player,
+            bound_notes,
+        } = midi_input;
         let mut run: bool = true;
         let (client, _status) =
             jack::Client::new("graphical oscillator", jack::ClientOptions::NO_START_SERVER)
@@ -24,7 +106,21 @@ pub fn start_jack_thread(
         // register ports
         let mut out_a = client.register_port("gosci_out_l", jack::AudioOut).unwrap();
         let mut out_b = client.register_port("gosci_out_r", jack::AudioOut).unwrap();
+        let mut part_outs: [(jack::Port<jack::AudioOut>, jack::Port<jack::AudioOut>); NUM_PARTS] =
+            std::array::from_fn(|index| {
+                (
+                    client
+                        .register_port(&format!("gosci_part{}_out_l", index + 1), jack::AudioOut)
+                        .unwrap(),
+                    client
+                        .register_port(&format!("gosci_part{}_out_r", index + 1), jack::AudioOut)
+                        .unwrap(),
+                )
+            });
         let midi_in = client.register_port("gosci_midi_in", jack::MidiIn).unwrap();
+        let mut midi_out = client
+            .register_port("gosci_midi_out", jack::MidiOut)
+            .unwrap();
 
         let mut frame_size = client.buffer_size() as usize;
         if client.set_buffer_size(frame_size as u32).is_ok() {
@@ -48,118 +144,91 @@ pub fn start_jack_thread(
             exit(-1);
         }
 
-        let mut tone_handling = ToneHandling::new();
-        let mut ctrl_msg = CtrlMsg {
-            size: 0,
-            intensity_am: 0.0,
-            freq_am: 0.0,
-            phase_am: 0.0,
-            intensity_fm: 0.0,
-            freq_fm: 0.0,
-            phase_fm: 0.0,
-            num_samples: frame_size,
-            volume: 1.0,
-            effect_params: None,
-        };
-
-        let mut adsr_envelope = Adsr::new(0.1, 0.2, 0.5, 0.2);
-        let mut effect_in_l: Vec<f32> = vec![1.0; frame_size];
-        let mut effect_in_r: Vec<f32> = vec![1.0; frame_size];
-
-        let mut effect_chain: Vec<Box<dyn Effect>> = Vec::new();
-        let mut overdrive = Overdrive::new();
-        overdrive.set_gain(1.0);
-        effect_chain.push(Box::new(overdrive));
+        // the first part plays all channels, the others wait for a channel from the gui
+        let mut engine = Engine::new(sample_rate as f32, frame_size);
+        engine.set_bound_notes(bound_notes);
+        for _ in 1..NUM_PARTS {
+            engine.add_part(PartConfig {
+                channel: MidiChannel::Off,
+                ..PartConfig::default()
+            });
+        }
+        recorder_tap.set_sample_rate(sample_rate as u32);
+        scope_tap.set_sample_rate(sample_rate as u32);
+        let mut midi_out_buffer = MidiOutBuffer::new();
+        let mut midi_clock = midi_output.clock_bpm.map(|bpm| {
+            let mut midi_clock = MidiClock::new(sample_rate as f64, bpm);
+            midi_clock.start();
+            midi_clock
+        });
 
         let process_callback = move |_: &jack::Client, ps: &jack::ProcessScope| -> jack::Control {
-            let show_p = midi_in.iter(ps);
-            for e in show_p {
+            // notes are applied at their offset in this period,
+            // the midi thread only gets a copy for display and controller mapping
+            for e in midi_in.iter(ps) {
+                engine.schedule_midi(e.time, e.bytes, NOTE_LENGTH);
+                if midi_output.echo && is_note(e.bytes) {
+                    midi_out_buffer.push(e.time, e.bytes);
+                }
                 let c: MidiMsgGeneric = e.into();
                 let _ = midi_sender.try_send(c);
             }
+            // midi from the file player has no offset inside the period
+            while let Ok(c) = rx_midi_player.try_recv() {
+                engine.schedule_midi(0, c.get_bytes(), NOTE_LENGTH);
+                if midi_output.echo && is_note(c.get_bytes()) {
+                    midi_out_buffer.push(0, c.get_bytes());
+                }
+                let _ = midi_sender.try_send(c);
+            }
             let out_a_p = out_a.as_mut_slice(ps);
             let out_b_p = out_b.as_mut_slice(ps);
 
-            // zero the ringbuffer
-            out_a_p.fill(0.0);
-            out_b_p.fill(0.0);
-
-            let mut multiply_out_l: Vec<f32> = vec![1.0; frame_size];
-            let mut multiply_out_r: Vec<f32> = vec![1.0; frame_size];
-
-            if let Ok(rx_ctrl_msg) = rx_ctrl.try_recv() {
-                ctrl_msg = rx_ctrl_msg;
-            };
-
-            if let Ok(rx_adsr_msg) = rx_adsr.try_recv() {
-                adsr_envelope = rx_adsr_msg;
-            };
-
-            if let Ok(rx_trigger_msg) = rx_trigger.try_recv() {
-                tone_handling.add_note_msg(rx_trigger_msg, adsr_envelope.clone(), frame_size);
-            };
-
-            //if ctrl_msg.effect.is_some() {
-            if !effect_chain.is_empty() {
-                let out_a_p_inter = effect_in_l.as_mut_slice();
-                let out_b_p_inter = effect_in_r.as_mut_slice();
+            for (part, event) in rx_part_event.try_iter() {
+                engine.handle_event_to(EventTarget::Part(part), event);
+            }
 
-                if let Some(ref effect_params) = ctrl_msg.effect_params {
-                    effect_chain[0].set_params(effect_params);
+            for (part, rx_trigger_msg) in trigger_queue
+                .rx_trigger
+                .try_iter()
+                .take(trigger_queue.budget)
+            {
+                if midi_output.echo {
+                    if let Some(engine_part) = engine.get_part(part) {
+                        let bytes = trigger_to_midi_bytes(
+                            &rx_trigger_msg,
+                            engine_part.get_config().channel,
+                        );
+                        midi_out_buffer.push(0, &bytes);
+                    }
                 }
-
-                tone_handling.process_tones(
-                    &ctrl_msg,
-                    out_a_p_inter,
-                    out_b_p_inter,
-                    &mut multiply_out_l,
-                    &mut multiply_out_r,
-                    frame_size,
-                );
-
-                tone_handling.normalize_out(
-                    out_a_p_inter,
-                    out_b_p_inter,
-                    &mut multiply_out_l,
-                    &mut multiply_out_r,
-                    frame_size,
-                );
-                // if let Some(ref mut effect) = ctrl_msg.effect {
-                // ToDo: check if there are new ctrl msgs for effects
-                /* ToDo delete, just testing purpose
-                if let Some(ref mut effect) = ctrl_msg.effect {
-                    effect.process_samples(
-                        Some(out_a_p_inter),
-                        Some(out_b_p_inter),
-                        Some(out_a_p),
-                        Some(out_b_p),
-                    );
-                };*/
-
-                effect_chain[0].process_samples(
-                    Some(out_a_p_inter),
-                    Some(out_b_p_inter),
-                    Some(out_a_p),
-                    Some(out_b_p),
-                );
-            } else {
-                tone_handling.process_tones(
-                    &ctrl_msg,
-                    out_a_p,
-                    out_b_p,
-                    &mut multiply_out_l,
-                    &mut multiply_out_r,
-                    frame_size,
-                );
-
-                tone_handling.normalize_out(
-                    out_a_p,
-                    out_b_p,
-                    &mut multiply_out_l,
-                    &mut multiply_out_r,
-                    frame_size,
+                engine.handle_event_to(
+                    EventTarget::Part(part),
+                    EngineEvent::Trigger(rx_trigger_msg),
                 );
             }
+            // triggers left over for the next periods
+            trigger_queue
+                .backlog
+                .store(trigger_queue.rx_trigger.len(), Ordering::Relaxed);
+
+            let mut part_outputs = part_outs
+                .each_mut()
+                .map(|(out_l, out_r)| (out_l.as_mut_slice(ps), out_r.as_mut_slice(ps)));
+            engine.process_routed(out_a_p, out_b_p, &mut part_outputs);
+            recorder_tap.push(out_a_p, out_b_p);
+            scope_tap.push(out_a_p, out_b_p);
+
+            for c in midi_output.rx_midi_out.try_iter() {
+                midi_out_buffer.push(0, c.get_bytes());
+            }
+            if let Some(ref mut midi_clock) = midi_clock {
+                midi_clock.process(ps.n_frames() as usize, &mut midi_out_buffer);
+            }
+            let mut midi_writer = midi_out.writer(ps);
+            midi_out_buffer.drain(|time, bytes| {
+                let _ = midi_writer.write(&jack::RawMidi { time, bytes });
+            });
 
             jack::Control::Continue
         };
@@ -1,21 +1,44 @@
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 use eframe::egui::ViewportBuilder;
 use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
 mod oscillator_gui;
-use oscillator_gui::OscillatorGui;
+use oscillator_gui::{default_part_configs, OscillatorGui, FILE_POLL_INTERVAL};
 use oscillator_lib::{
+    bound_notes::BoundNotes,
+    controller_profile::{load_controller_profile, merge_midi_mappings},
+    equalizer::default_eq_bands,
+    file_watcher::watch_files,
     jackmidi::{MidiMsgAdvanced, MidiMsgGeneric},
     midi_functions::{
         parse_json_file_to_midi_functions_with_midi_msgs_advanced,
-        reverse_map_midi_functions2midi_advanced_msgs,
+        reverse_map_midi_functions2midi_advanced_msgs, MidiBinding, MidiFunction,
     },
-    midi_process::midi_process_fct,
+    midi_monitor::{MidiMonitor, MIDI_MONITOR_CAPACITY},
+    midi_player::MidiPlayer,
+    midi_process::{midi_process_fct, MidiLearn},
+    patch::{parse_json_file_to_patch, Patch},
+    recorder::Recorder,
+    scope::Scope,
+    smf::Smf,
+    stereo::PanMode,
+    wav::SampleFormat,
 };
 mod jackprocess;
 use bus::Bus;
 use clap::Parser;
-use jackprocess::start_jack_thread;
+use jackprocess::{
+    start_jack_thread, MidiInput, MidiOutput, OutputTaps, TriggerQueue, DEFAULT_TRIGGER_BUDGET,
+    NUM_PARTS,
+};
 use oscillator_lib::trigger_note_msg::TriggerNoteMsg;
+use piano::Piano;
+use scope_view::ScopeView;
+use spectrum_view::SpectrumView;
+mod piano;
+mod scope_view;
+mod spectrum_view;
 mod status_button;
 
 #[derive(Parser, Debug)]
@@ -24,52 +47,166 @@ struct Args {
     /// midi_mapping_filepath
     #[arg(short, long, value_name = "filepath")]
     pub midi_mapping_filepath: Option<String>,
+    /// built-in controller profile (generic_8_knob, nanokontrol, mpk_mini) or a mapping file,
+    /// the midi mapping overrides its functions
+    #[arg(long, value_name = "name")]
+    pub controller_profile: Option<String>,
+    /// patch file of the next part, repeat for further parts
+    #[arg(long = "patch", value_name = "filepath")]
+    pub patch_filepaths: Vec<String>,
+    /// reload the midi mapping and patch files when they change
+    #[arg(long)]
+    pub watch: bool,
+    /// standard midi file to play on start
+    #[arg(long, value_name = "filepath")]
+    pub midi_file: Option<String>,
+    /// maximum number of triggers handled per jack period
+    #[arg(long, value_name = "count", default_value_t = DEFAULT_TRIGGER_BUDGET)]
+    pub trigger_budget: usize,
+    /// echo played notes on gosci_midi_out
+    #[arg(long)]
+    pub midi_echo: bool,
+    /// send midi clock on gosci_midi_out with this tempo
+    #[arg(long, value_name = "bpm")]
+    pub midi_clock: Option<f64>,
 }
 
 fn main() {
-    let midi_functions_with_midi_advanced_msgs: Result<
-        HashMap<String, Vec<MidiMsgAdvanced>>,
-        String,
-    > = Args::parse().midi_mapping_filepath.map_or_else(
-        || Ok(HashMap::<String, Vec<MidiMsgAdvanced>>::new()),
-        |filepath| {
-            // Todo: parse filepath
-            println!("found midi config file: {}", filepath.as_str());
-            parse_json_file_to_midi_functions_with_midi_msgs_advanced(&filepath)
+    let args = Args::parse();
+    let controller_profile = args.controller_profile.as_ref().map_or_else(
+        || Ok(HashMap::<String, Vec<MidiBinding>>::new()),
+        |profile| {
+            println!("controller profile: {}", profile.as_str());
+            load_controller_profile(profile)
         },
     );
+    let controller_profile = controller_profile.unwrap_or_else(|e| {
+        println!("could not load controller profile e: {}", e);
+        HashMap::new()
+    });
+    let midi_functions_with_midi_advanced_msgs: Result<HashMap<String, Vec<MidiBinding>>, String> =
+        args.midi_mapping_filepath.as_ref().map_or_else(
+            || Ok(HashMap::<String, Vec<MidiBinding>>::new()),
+            |filepath| {
+                // Todo: parse filepath
+                println!("found midi config file: {}", filepath.as_str());
+                parse_json_file_to_midi_functions_with_midi_msgs_advanced(filepath)
+            },
+        );
+    // the gui sends controller feedback to the mapped controllers
+    let midi_functions: HashMap<String, Vec<MidiBinding>> =
+        match midi_functions_with_midi_advanced_msgs {
+            Ok(midi_functions_with_midi_advanced_msgs) => merge_midi_mappings(
+                controller_profile.clone(),
+                midi_functions_with_midi_advanced_msgs,
+            ),
+            Err(e) => {
+                println!("could not load midi mapping e: {}", e);
+                controller_profile.clone()
+            }
+        };
     // create a reverse Hashmap
-    let mut midi_advanced_msgs2midi_functions: HashMap<MidiMsgAdvanced, Vec<String>> =
-        HashMap::new();
-    if let Ok(midi_functions_with_midi_advanced_msgs) = midi_functions_with_midi_advanced_msgs {
-        midi_advanced_msgs2midi_functions =
-            reverse_map_midi_functions2midi_advanced_msgs(midi_functions_with_midi_advanced_msgs);
-    }
+    let midi_advanced_msgs2midi_functions: HashMap<MidiMsgAdvanced, Vec<MidiFunction>> =
+        reverse_map_midi_functions2midi_advanced_msgs(midi_functions.clone());
+    // pads bound to a function set values instead of playing notes
+    let bound_notes = BoundNotes::from_midi_mapping(&midi_advanced_msgs2midi_functions);
     let mut tx_close_bus = Bus::new(10);
     let rx_close_bus1 = tx_close_bus.add_rx();
     let rx_close_bus2 = tx_close_bus.add_rx();
-    let (tx_ctrl, rx_ctrl) = unbounded();
-    let (tx_adsr, rx_adsr) = unbounded();
+    let mut part_patches = vec![Patch::default(); NUM_PARTS];
+    let mut patch_file_paths: Vec<Option<String>> = vec![None; NUM_PARTS];
+    for (part, patch_filepath) in args.patch_filepaths.iter().take(NUM_PARTS).enumerate() {
+        match parse_json_file_to_patch(patch_filepath) {
+            Ok(patch) => part_patches[part] = patch,
+            Err(e) => println!("could not load patch e: {}", e),
+        }
+        patch_file_paths[part] = Some(patch_filepath.clone());
+    }
+    let rx_file_changed = args.watch.then(|| {
+        let (tx_file_changed, rx_file_changed) = unbounded();
+        let filepaths = args
+            .midi_mapping_filepath
+            .iter()
+            .chain(patch_file_paths.iter().flatten())
+            .cloned()
+            .collect();
+        watch_files(
+            filepaths,
+            FILE_POLL_INTERVAL,
+            tx_file_changed,
+            tx_close_bus.add_rx(),
+        );
+        rx_file_changed
+    });
+    let (tx_part_event, rx_part_event) = unbounded();
     let (tx_trigger, rx_trigger) = unbounded();
-    let tx_trigger2 = tx_trigger.clone();
     let (tx_note_velocity, rx_note_velocity): (Sender<TriggerNoteMsg>, Receiver<TriggerNoteMsg>) =
         unbounded();
     let (midi_sender, midi_receiver): (Sender<MidiMsgGeneric>, Receiver<MidiMsgGeneric>) =
         unbounded();
+    let (tx_midi_player, rx_midi_player): (Sender<MidiMsgGeneric>, Receiver<MidiMsgGeneric>) =
+        unbounded();
     let (tx_midi_ctrl, rx_midi_ctrl): (Sender<(String, f32)>, Receiver<(String, f32)>) =
         unbounded();
+    let (tx_midi_learn, rx_midi_learn) = unbounded();
+    let (tx_midi_learned, rx_midi_learned) = unbounded();
+    // clock messages must not pile up while the gui does not repaint
+    let (tx_midi_monitor, rx_midi_monitor) = bounded(MIDI_MONITOR_CAPACITY);
+    let midi_monitor = MidiMonitor::new();
+    let (tx_midi_out, rx_midi_out): (Sender<MidiMsgGeneric>, Receiver<MidiMsgGeneric>) =
+        unbounded();
     // midi msg test thread
     let midi_thread = midi_process_fct(
         midi_receiver,
         tx_note_velocity,
-        tx_trigger2,
         rx_close_bus1,
         Some(tx_midi_ctrl),
         Some(midi_advanced_msgs2midi_functions),
+        Some(MidiLearn {
+            rx_cmd: rx_midi_learn,
+            tx_learned: tx_midi_learned,
+            bound_notes: bound_notes.clone(),
+        }),
+        Some(midi_monitor.tap(tx_midi_monitor)),
     );
 
-    let jack_thread = start_jack_thread(rx_close_bus2, rx_ctrl, rx_adsr, rx_trigger, midi_sender);
-    let graphical_osci_app = OscillatorGui {
+    if let Some(midi_filepath) = args.midi_file {
+        match Smf::from_file(&midi_filepath) {
+            Ok(smf) => {
+                println!("play midi file: {}", midi_filepath.as_str());
+                MidiPlayer::from_smf(&smf).play(tx_midi_player, tx_close_bus.add_rx());
+            }
+            Err(e) => println!("could not load midi file e: {}", e),
+        }
+    }
+
+    let (recorder, recorder_tap) = Recorder::new();
+    let (scope, scope_tap) = Scope::new();
+    let trigger_backlog = Arc::new(AtomicUsize::new(0));
+    let jack_thread = start_jack_thread(
+        rx_close_bus2,
+        rx_part_event,
+        TriggerQueue {
+            rx_trigger,
+            budget: args.trigger_budget.max(1),
+            backlog: trigger_backlog.clone(),
+        },
+        MidiInput {
+            midi_sender,
+            rx_midi_player,
+            bound_notes,
+        },
+        OutputTaps {
+            recorder_tap,
+            scope_tap,
+        },
+        MidiOutput {
+            rx_midi_out,
+            echo: args.midi_echo,
+            clock_bpm: args.midi_clock,
+        },
+    );
+    let mut graphical_osci_app = OscillatorGui {
         freq: 440.0,
         velocity: 1.0,
         volume: 1.0,
@@ -83,13 +220,11 @@ fn main() {
         decay: 0.2,
         sustain: 0.3,
         release: 0.2,
-        num_samples: 48000,
         length: 96000,
         jack_thread: Some(jack_thread),
         midi_thread: Some(midi_thread),
         tx_close: Some(tx_close_bus),
-        tx_ctrl: Some(tx_ctrl),
-        tx_adsr: Some(tx_adsr),
+        tx_part_event: Some(tx_part_event),
         tx_trigger: Some(tx_trigger),
         rx_note_velocity: Some(rx_note_velocity),
         rx_midi_ctrl: Some(rx_midi_ctrl),
@@ -97,7 +232,53 @@ fn main() {
         init_repainter_midi_ctrl: true,
         overdrive_toggle: false,
         overdrive: 1.0,
+        equalizer_toggle: false,
+        equalizer_bands: default_eq_bands(),
+        pan: 0.0,
+        pan_spread: 0.0,
+        pan_mode: PanMode::NoteNumber,
+        stereo_width: 1.0,
+        pitch_bend: 0.0,
+        pitch_bend_range: 2.0,
+        vibrato_rate: 5.0,
+        vibrato_depth: 0.5,
+        autopan_toggle: false,
+        autopan_rate: 1.0,
+        autopan_depth: 1.0,
+        recorder: Some(recorder),
+        record_file_path: "recording.wav".to_string(),
+        record_sample_format: SampleFormat::Int16,
+        record_status: String::new(),
+        trigger_backlog: Some(trigger_backlog),
+        max_trigger_backlog: 0,
+        part_patches,
+        part_configs: default_part_configs(),
+        sent_part_patches: vec![None; NUM_PARTS],
+        sent_part_configs: vec![None; NUM_PARTS],
+        selected_part: 0,
+        tx_midi_out: Some(tx_midi_out),
+        midi_functions,
+        midi_feedback_values: HashMap::new(),
+        tx_midi_learn: Some(tx_midi_learn),
+        rx_midi_learned: Some(rx_midi_learned),
+        midi_learn_function: None,
+        midi_mapping_file_path: args
+            .midi_mapping_filepath
+            .unwrap_or_else(|| "rust_graphical_oscillator_midi_mapping.json".to_string()),
+        midi_mapping_status: String::new(),
+        controller_profile,
+        patch_file_paths,
+        patch_status: String::new(),
+        rx_file_changed,
+        rx_midi_monitor: Some(rx_midi_monitor),
+        midi_monitor,
+        midi_monitor_open: false,
+        piano: Piano::new(),
+        scope_view: ScopeView::new(Some(scope.clone())),
+        spectrum_view: SpectrumView::new(Some(scope)),
     };
+    let patch = graphical_osci_app.part_patches[0].clone();
+    graphical_osci_app.set_patch(&patch);
     let options = eframe::NativeOptions {
         viewport: ViewportBuilder::default().with_inner_size([600.0, 600.0]),
         ..Default::default()
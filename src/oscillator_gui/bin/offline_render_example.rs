@@ -0,0 +1,52 @@
+use oscillator_lib::adsr::Adsr;
+use oscillator_lib::engine::{Engine, EngineEvent};
+use oscillator_lib::trigger_note_msg::{NoteType, TriggerNoteMsg};
+use oscillator_lib::wav::{write_wav_file, SampleFormat, WavSpec};
+
+fn main() {
+    let sample_rate: f32 = 48000.0;
+    let frame_size: usize = 1024;
+    let mut engine = Engine::new(sample_rate, frame_size);
+
+    engine.schedule(0, EngineEvent::Adsr(Adsr::new(0.1, 0.2, 0.5, 0.2)));
+    engine.schedule(
+        0,
+        EngineEvent::Trigger(TriggerNoteMsg {
+            note_type: NoteType::NoteOn,
+            freq: 440.0,
+            velocity: 1.0,
+            length: 96000,
+        }),
+    );
+    engine.schedule(
+        24000,
+        EngineEvent::Trigger(TriggerNoteMsg {
+            note_type: NoteType::NoteOff,
+            freq: 440.0,
+            velocity: 0.0,
+            length: 96000,
+        }),
+    );
+
+    let (out_l, out_r) = engine.render(48000);
+    let peak = |buffer: &[f32]| buffer.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    println!(
+        "rendered {} frames, peak l: {}, peak r: {}",
+        out_l.len(),
+        peak(&out_l),
+        peak(&out_r)
+    );
+
+    // optional: offline_render_example output.wav
+    if let Some(file_path) = std::env::args().nth(1) {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: sample_rate as u32,
+            sample_format: SampleFormat::Int24,
+        };
+        match write_wav_file(&file_path, spec, &[&out_l, &out_r]) {
+            Ok(()) => println!("wrote {}", file_path),
+            Err(e) => println!("could not write wav file e: {}", e),
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use crate::jackprocess::NUM_PARTS;
+use crate::piano::{Piano, PianoEvent};
+use crate::scope_view::ScopeView;
+use crate::spectrum_view::SpectrumView;
 use crate::status_button::status_button;
 use bus::Bus;
 use crossbeam_channel::{unbounded, Receiver, Sender};
@@ -6,13 +10,35 @@ use eframe::{
     glow::Context,
 };
 use egui_plot::{Line, Plot, PlotPoints};
-use oscillator_lib::adsr::Adsr;
-use oscillator_lib::ctrl_msg::{CtrlMsg, ParameterMap};
+use oscillator_lib::biquad::BiquadType;
+use oscillator_lib::controller_profile::merge_midi_mappings;
+use oscillator_lib::effect::Effect;
+use oscillator_lib::engine::EngineEvent;
+use oscillator_lib::equalizer::{default_eq_bands, EqBand, Equalizer};
+use oscillator_lib::jackmidi::{MidiMsgAdvanced, MidiMsgGeneric};
+use oscillator_lib::midi_functions::{
+    default_midi_function_range, parse_json_file_to_midi_functions_with_midi_msgs_advanced,
+    reverse_map_midi_functions2midi_advanced_msgs,
+    write_midi_functions_with_midi_msgs_advanced_to_json_file, MidiBinding,
+};
+use oscillator_lib::midi_monitor::{MidiMonitor, MidiMonitorEntry, MidiMonitorKind};
+use oscillator_lib::midi_out::control_feedback;
+use oscillator_lib::midi_process::MidiLearnCmd;
+use oscillator_lib::part::{MidiChannel, PartConfig, PartRouting};
+use oscillator_lib::patch::{parse_json_file_to_patch, Patch};
+use oscillator_lib::recorder::Recorder;
+use oscillator_lib::stereo::PanMode;
 use oscillator_lib::trigger_note_msg::{NoteType, TriggerNoteMsg};
-use oscillator_lib::wave_gen::SineWave;
+use oscillator_lib::util::{freq_to_note, to_freq_f32};
+use oscillator_lib::wav::SampleFormat;
 use std::collections::HashMap;
-use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
+
+/// how often the mapping and patch files are checked for changes
+pub const FILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
 
 pub struct OscillatorGui {
     pub freq: f32,
@@ -28,20 +54,71 @@ pub struct OscillatorGui {
     pub decay: f32,
     pub sustain: f32,
     pub release: f32,
-    pub num_samples: usize,
     pub length: usize,
     pub jack_thread: Option<std::thread::JoinHandle<()>>,
     pub midi_thread: Option<std::thread::JoinHandle<()>>,
     pub tx_close: Option<Bus<bool>>,
-    pub tx_ctrl: Option<Sender<CtrlMsg>>,
-    pub tx_adsr: Option<Sender<Adsr>>,
-    pub tx_trigger: Option<Sender<TriggerNoteMsg>>,
+    /// (part index, event)
+    pub tx_part_event: Option<Sender<(usize, EngineEvent)>>,
+    pub tx_trigger: Option<Sender<(usize, TriggerNoteMsg)>>,
     pub rx_note_velocity: Option<Receiver<TriggerNoteMsg>>,
     pub rx_midi_ctrl: Option<Receiver<(String, f32)>>,
     pub init_repainter_note_velocity: bool,
     pub init_repainter_midi_ctrl: bool,
     pub overdrive_toggle: bool,
     pub overdrive: f32,
+    pub equalizer_toggle: bool,
+    pub equalizer_bands: Vec<EqBand>,
+    pub pan: f32,
+    pub pan_spread: f32,
+    pub pan_mode: PanMode,
+    pub stereo_width: f32,
+    pub pitch_bend: f32,
+    pub pitch_bend_range: f32,
+    pub vibrato_rate: f32,
+    pub vibrato_depth: f32,
+    pub autopan_toggle: bool,
+    pub autopan_rate: f32,
+    pub autopan_depth: f32,
+    pub recorder: Option<Recorder>,
+    pub record_file_path: String,
+    pub record_sample_format: SampleFormat,
+    pub record_status: String,
+    pub trigger_backlog: Option<Arc<AtomicUsize>>,
+    pub max_trigger_backlog: usize,
+    /// patches of all parts, the selected one is edited in the fields above
+    pub part_patches: Vec<Patch>,
+    pub part_configs: Vec<PartConfig>,
+    /// last patches and configs sent to the engine, None before the first send
+    pub sent_part_patches: Vec<Option<Patch>>,
+    pub sent_part_configs: Vec<Option<PartConfig>>,
+    pub selected_part: usize,
+    pub tx_midi_out: Option<Sender<MidiMsgGeneric>>,
+    /// mapping of the midi functions to controllers, for feedback
+    pub midi_functions: HashMap<String, Vec<MidiBinding>>,
+    /// last value of each midi function sent to or received from the controllers
+    pub midi_feedback_values: HashMap<String, f32>,
+    pub tx_midi_learn: Option<Sender<MidiLearnCmd>>,
+    pub rx_midi_learned: Option<Receiver<(String, MidiMsgAdvanced)>>,
+    /// function waiting for a controller message
+    pub midi_learn_function: Option<String>,
+    pub midi_mapping_file_path: String,
+    pub midi_mapping_status: String,
+    /// bindings of the controller profile, the mapping file overrides them on reload
+    pub controller_profile: HashMap<String, Vec<MidiBinding>>,
+    /// patch file of each part, reloaded when it changes
+    pub patch_file_paths: Vec<Option<String>>,
+    pub patch_status: String,
+    /// paths of changed mapping and patch files
+    pub rx_file_changed: Option<Receiver<String>>,
+    pub rx_midi_monitor: Option<Receiver<MidiMonitorEntry>>,
+    pub midi_monitor: MidiMonitor,
+    pub midi_monitor_open: bool,
+    pub piano: Piano,
+    /// output of the engine as it is played
+    pub scope_view: ScopeView,
+    /// spectrum of the same output, e.g. the sidebands of AM and FM
+    pub spectrum_view: SpectrumView,
 }
 
 impl Default for OscillatorGui {
@@ -60,13 +137,11 @@ impl Default for OscillatorGui {
             decay: 0.2,
             sustain: 0.3,
             release: 0.2,
-            num_samples: 48000,
             length: 96000,
             jack_thread: None,
             midi_thread: None,
             tx_close: None,
-            tx_ctrl: None,
-            tx_adsr: None,
+            tx_part_event: None,
             tx_trigger: None,
             rx_note_velocity: None,
             rx_midi_ctrl: None,
@@ -74,8 +149,391 @@ impl Default for OscillatorGui {
             init_repainter_midi_ctrl: true,
             overdrive_toggle: false,
             overdrive: 1.0,
+            equalizer_toggle: false,
+            equalizer_bands: default_eq_bands(),
+            pan: 0.0,
+            pan_spread: 0.0,
+            pan_mode: PanMode::NoteNumber,
+            stereo_width: 1.0,
+            pitch_bend: 0.0,
+            pitch_bend_range: 2.0,
+            vibrato_rate: 5.0,
+            vibrato_depth: 0.5,
+            autopan_toggle: false,
+            autopan_rate: 1.0,
+            autopan_depth: 1.0,
+            recorder: None,
+            record_file_path: "recording.wav".to_string(),
+            record_sample_format: SampleFormat::Int16,
+            record_status: String::new(),
+            trigger_backlog: None,
+            max_trigger_backlog: 0,
+            part_patches: vec![Patch::default(); NUM_PARTS],
+            part_configs: default_part_configs(),
+            sent_part_patches: vec![None; NUM_PARTS],
+            sent_part_configs: vec![None; NUM_PARTS],
+            selected_part: 0,
+            tx_midi_out: None,
+            midi_functions: HashMap::new(),
+            midi_feedback_values: HashMap::new(),
+            tx_midi_learn: None,
+            rx_midi_learned: None,
+            midi_learn_function: None,
+            midi_mapping_file_path: "rust_graphical_oscillator_midi_mapping.json".to_string(),
+            midi_mapping_status: String::new(),
+            controller_profile: HashMap::new(),
+            patch_file_paths: vec![None; NUM_PARTS],
+            patch_status: String::new(),
+            rx_file_changed: None,
+            rx_midi_monitor: None,
+            midi_monitor: MidiMonitor::new(),
+            midi_monitor_open: false,
+            piano: Piano::new(),
+            scope_view: ScopeView::new(None),
+            spectrum_view: SpectrumView::new(None),
+        }
+    }
+}
+
+/// first part on all channels, the others off
+pub fn default_part_configs() -> Vec<PartConfig> {
+    (0..NUM_PARTS)
+        .map(|index| PartConfig {
+            channel: if index == 0 {
+                MidiChannel::Omni
+            } else {
+                MidiChannel::Off
+            },
+            ..PartConfig::default()
+        })
+        .collect()
+}
+
+impl OscillatorGui {
+    pub fn get_patch(&self) -> Patch {
+        Patch {
+            volume: self.volume,
+            intensity_am: self.intensity_am,
+            freq_am: self.freq_am,
+            phase_am: self.phase_am,
+            intensity_fm: self.intensity_fm,
+            freq_fm: self.freq_fm,
+            phase_fm: self.phase_fm,
+            attack: self.attack,
+            decay: self.decay,
+            sustain: self.sustain,
+            release: self.release,
+            length: self.length,
+            pan: self.pan,
+            pan_spread: self.pan_spread,
+            pan_mode: self.pan_mode,
+            stereo_width: self.stereo_width,
+            pitch_bend_range: self.pitch_bend_range,
+            vibrato_rate: self.vibrato_rate,
+            vibrato_depth: self.vibrato_depth,
+            overdrive_toggle: self.overdrive_toggle,
+            overdrive: self.overdrive,
+            equalizer_toggle: self.equalizer_toggle,
+            equalizer_bands: self.equalizer_bands.clone(),
+            autopan_toggle: self.autopan_toggle,
+            autopan_rate: self.autopan_rate,
+            autopan_depth: self.autopan_depth,
+        }
+    }
+
+    pub fn set_patch(&mut self, patch: &Patch) {
+        self.volume = patch.volume;
+        self.intensity_am = patch.intensity_am;
+        self.freq_am = patch.freq_am;
+        self.phase_am = patch.phase_am;
+        self.intensity_fm = patch.intensity_fm;
+        self.freq_fm = patch.freq_fm;
+        self.phase_fm = patch.phase_fm;
+        self.attack = patch.attack;
+        self.decay = patch.decay;
+        self.sustain = patch.sustain;
+        self.release = patch.release;
+        self.length = patch.length;
+        self.pan = patch.pan;
+        self.pan_spread = patch.pan_spread;
+        self.pan_mode = patch.pan_mode;
+        self.stereo_width = patch.stereo_width;
+        self.pitch_bend_range = patch.pitch_bend_range;
+        self.vibrato_rate = patch.vibrato_rate;
+        self.vibrato_depth = patch.vibrato_depth;
+        self.overdrive_toggle = patch.overdrive_toggle;
+        self.overdrive = patch.overdrive;
+        self.equalizer_toggle = patch.equalizer_toggle;
+        self.equalizer_bands = patch.equalizer_bands.clone();
+        self.autopan_toggle = patch.autopan_toggle;
+        self.autopan_rate = patch.autopan_rate;
+        self.autopan_depth = patch.autopan_depth;
+    }
+
+    /// current values of the midi functions
+    fn get_midi_function_values(&self) -> [(&'static str, f32); 12] {
+        [
+            ("intensity_am", self.intensity_am),
+            ("freq_am", self.freq_am),
+            ("phase_am", self.phase_am),
+            ("intensity_fm", self.intensity_fm),
+            ("freq_fm", self.freq_fm),
+            ("phase_fm", self.phase_fm),
+            ("overdrive_gain", self.overdrive),
+            ("pan", self.pan),
+            ("pan_spread", self.pan_spread),
+            ("stereo_width", self.stereo_width),
+            ("vibrato_rate", self.vibrato_rate),
+            ("vibrato_depth", self.vibrato_depth),
+        ]
+    }
+
+    /// move the mapped controllers to parameters changed in the gui,
+    /// relative encoders continue from the changed values
+    fn send_midi_feedback(&mut self) {
+        for (function, value) in self.get_midi_function_values() {
+            let Some(midi_bindings) = self.midi_functions.get(function) else {
+                continue;
+            };
+            let default_range = default_midi_function_range(function);
+            if let Some(last_value) = self.midi_feedback_values.get(function) {
+                // below the resolution of a 7 bit controller
+                if (last_value - value).abs() < 0.5 / 127.0 * (default_range.1 - default_range.0) {
+                    continue;
+                }
+            }
+            self.midi_feedback_values
+                .insert(function.to_string(), value);
+            if let Some(ref tx_midi_learn) = self.tx_midi_learn {
+                let _ = tx_midi_learn.send(MidiLearnCmd::Value(function.to_string(), value));
+            }
+            let Some(ref tx_midi_out) = self.tx_midi_out else {
+                continue;
+            };
+            for midi_binding in midi_bindings {
+                let norm_value = midi_binding.scaling.unscale(value, default_range);
+                for msg in control_feedback(&midi_binding.msg, norm_value) {
+                    let _ = tx_midi_out.send(msg);
+                }
+            }
+        }
+    }
+
+    /// right click menu of a slider to bind a controller to its midi function
+    fn midi_learn_menu(&mut self, response: egui::Response, function: &str) {
+        response.context_menu(|ui| {
+            if ui.button("MIDI learn").clicked() {
+                self.send_midi_learn_cmd(MidiLearnCmd::Learn(function.to_string()));
+                self.midi_learn_function = Some(function.to_string());
+                ui.close_menu();
+            }
+            if self.midi_functions.contains_key(function) && ui.button("MIDI forget").clicked() {
+                self.send_midi_learn_cmd(MidiLearnCmd::Forget(function.to_string()));
+                self.midi_functions.remove(function);
+                self.midi_mapping_status = format!("forgot {}", function);
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn send_midi_learn_cmd(&self, cmd: MidiLearnCmd) {
+        if let Some(ref tx_midi_learn) = self.tx_midi_learn {
+            if let Err(e) = tx_midi_learn.send(cmd) {
+                println!("could not send midi learn cmd e: {}", e);
+            }
+        }
+    }
+
+    fn receive_midi_learned(&mut self) {
+        let Some(ref rx_midi_learned) = self.rx_midi_learned else {
+            return;
+        };
+        while let Ok((function, midi_advanced_msg)) = rx_midi_learned.try_recv() {
+            self.midi_functions
+                .insert(function.clone(), vec![MidiBinding::new(midi_advanced_msg)]);
+            // the controller gets the current value
+            self.midi_feedback_values.remove(&function);
+            if self.midi_learn_function.as_ref() == Some(&function) {
+                self.midi_learn_function = None;
+            }
+            self.midi_mapping_status = format!("learned {}", function);
+        }
+    }
+
+    /// reload changed patch files into their parts, any other changed file is the midi mapping
+    fn receive_file_changed(&mut self) {
+        let Some(ref rx_file_changed) = self.rx_file_changed else {
+            return;
+        };
+        let changed: Vec<String> = rx_file_changed.try_iter().collect();
+        for filepath in changed {
+            match self
+                .patch_file_paths
+                .iter()
+                .position(|patch_file_path| patch_file_path.as_ref() == Some(&filepath))
+            {
+                Some(part) => self.reload_patch(part, &filepath),
+                None => self.reload_midi_mapping(&filepath),
+            }
+        }
+    }
+
+    fn reload_patch(&mut self, part: usize, filepath: &str) {
+        match parse_json_file_to_patch(filepath) {
+            Ok(patch) => {
+                if part == self.selected_part {
+                    self.set_patch(&patch);
+                }
+                self.part_patches[part] = patch;
+                self.patch_status = format!("reloaded {}", filepath);
+            }
+            Err(e) => self.patch_status = format!("could not reload {}: {}", filepath, e),
+        }
+    }
+
+    /// swap the mapping of the midi thread, a broken file keeps the running mapping
+    fn reload_midi_mapping(&mut self, filepath: &str) {
+        match parse_json_file_to_midi_functions_with_midi_msgs_advanced(&filepath.to_string()) {
+            Ok(midi_mapping) => {
+                self.midi_functions =
+                    merge_midi_mappings(self.controller_profile.clone(), midi_mapping);
+                self.send_midi_learn_cmd(MidiLearnCmd::Mapping(
+                    reverse_map_midi_functions2midi_advanced_msgs(self.midi_functions.clone()),
+                ));
+                // the controllers of the new bindings get the current values
+                self.midi_feedback_values.clear();
+                self.midi_mapping_status = format!("reloaded {}", filepath);
+            }
+            Err(e) => self.midi_mapping_status = format!("could not reload {}: {}", filepath, e),
+        }
+    }
+
+    /// notes of the piano go to the selected part like the trigger button
+    fn send_piano_events(&self, piano_events: Vec<PianoEvent>) {
+        let Some(ref tx_trigger) = self.tx_trigger else {
+            return;
+        };
+        for (note, on) in piano_events {
+            let trigger_note = TriggerNoteMsg {
+                note_type: if on {
+                    NoteType::NoteOn
+                } else {
+                    NoteType::NoteOff
+                },
+                freq: to_freq_f32(note),
+                velocity: self.piano.velocity,
+                length: self.length,
+            };
+            if let Err(e) = tx_trigger.send((self.selected_part, trigger_note)) {
+                println!("could not send piano note e: {}", e);
+            }
+        }
+    }
+
+    fn receive_midi_monitor(&mut self) {
+        let Some(ref rx_midi_monitor) = self.rx_midi_monitor else {
+            return;
+        };
+        for entry in rx_midi_monitor.try_iter() {
+            self.midi_monitor.push(entry);
+        }
+    }
+
+    /// incoming messages with time, channel and the MidiMsgAdvanced for the mapping file
+    fn show_midi_monitor(&mut self, ctx: &egui::Context) {
+        let mut open = self.midi_monitor_open;
+        egui::Window::new("MIDI monitor")
+            .open(&mut open)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                let monitor = &mut self.midi_monitor;
+                ui.horizontal(|ui| {
+                    ui.toggle_value(&mut monitor.paused, "pause");
+                    if ui.button("clear").clicked() {
+                        monitor.clear();
+                    }
+                    ui.label("Channel: ");
+                    egui::ComboBox::from_id_source("midi_monitor_channel")
+                        .selected_text(
+                            monitor
+                                .channel
+                                .map_or("all".to_string(), |channel| format!("{}", channel + 1)),
+                        )
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut monitor.channel, None, "all");
+                            for channel in 0..16 {
+                                ui.selectable_value(
+                                    &mut monitor.channel,
+                                    Some(channel),
+                                    format!("{}", channel + 1),
+                                );
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    for (kind, shown) in MidiMonitorKind::ALL.iter().zip(monitor.kinds.iter_mut()) {
+                        ui.checkbox(shown, kind.to_string());
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| {
+                        for entry in monitor.filtered() {
+                            let channel = entry
+                                .channel
+                                .map_or("-".to_string(), |channel| format!("{}", channel + 1));
+                            let mut text = format!(
+                                "{:10.3} ch {:>2}  {}",
+                                monitor.get_timestamp(entry),
+                                channel,
+                                entry.message
+                            );
+                            if let Some(ref advanced) = entry.advanced {
+                                text.push_str(&format!("  -> {}", advanced));
+                            }
+                            ui.monospace(text);
+                        }
+                    });
+            });
+        self.midi_monitor_open = open;
+    }
+
+    /// patch and config of the parts that changed since they were last sent,
+    /// the ctrl msg maps must not be allocated and dropped in the jack callback on every repaint
+    fn send_part_events(&mut self) {
+        let Some(ref tx_part_event) = self.tx_part_event else {
+            return;
+        };
+        for (part, (patch, config)) in self
+            .part_patches
+            .iter()
+            .zip(self.part_configs.iter())
+            .enumerate()
+        {
+            if self.sent_part_patches[part].as_ref() != Some(patch) {
+                let _ = tx_part_event.send((part, EngineEvent::Ctrl(patch.to_ctrl_msg())));
+                let _ = tx_part_event.send((part, EngineEvent::Adsr(patch.to_adsr())));
+                self.sent_part_patches[part] = Some(patch.clone());
+            }
+            if self.sent_part_configs[part] != Some(*config) {
+                let _ = tx_part_event.send((part, EngineEvent::PartConfig(*config)));
+                self.sent_part_configs[part] = Some(*config);
+            }
         }
     }
+
+    /// keep the edited patch and load the one of the newly selected part
+    pub fn select_part(&mut self, part: usize) {
+        if part == self.selected_part || part >= self.part_patches.len() {
+            return;
+        }
+        self.part_patches[self.selected_part] = self.get_patch();
+        self.selected_part = part;
+        let patch = self.part_patches[part].clone();
+        self.set_patch(&patch);
+    }
 }
 
 impl eframe::App for OscillatorGui {
@@ -118,88 +576,90 @@ impl eframe::App for OscillatorGui {
             }
             for (function, value) in received_midi_ctrl_messages {
                 match function.as_str() {
+                    // values are already scaled to the range of the function by midi_process
                     "intensity_am" => self.intensity_am = value,
-                    "freq_am" => self.freq_am = value * 10.0,
-                    "phase_am" => self.phase_am = value * 2.0 * PI,
-                    "intensity_fm" => self.intensity_fm = value * 100.0,
-                    "freq_fm" => self.freq_fm = value * 10.0,
-                    "phase_fm" => self.phase_fm = value * 2.0 * PI,
-                    "overdrive_gain" => self.overdrive = value * 10.0,
+                    "freq_am" => self.freq_am = value,
+                    "phase_am" => self.phase_am = value,
+                    "intensity_fm" => self.intensity_fm = value,
+                    "freq_fm" => self.freq_fm = value,
+                    "phase_fm" => self.phase_fm = value,
+                    "overdrive_gain" => self.overdrive = value,
+                    "pan" => self.pan = value,
+                    "pan_spread" => self.pan_spread = value,
+                    "stereo_width" => self.stereo_width = value,
+                    "pitch_bend" => self.pitch_bend = value,
+                    "pitch_bend_range" => self.pitch_bend_range = value.min(24.0),
+                    "vibrato_rate" => self.vibrato_rate = value,
+                    "vibrato_depth" => self.vibrato_depth = value,
                     &_ => (),
                 }
+                // no feedback to the controller the value came from
+                self.midi_feedback_values.insert(function, value);
             }
         }
-        let my_sine = SineWave::new(
-            self.freq as f64,
-            self.volume as f64,
-            self.intensity_am as f64,
-            self.freq_am as f64,
-            self.phase_am as f64,
-            self.intensity_fm as f64,
-            self.freq_fm as f64,
-            self.phase_fm as f64,
-            48000.0,
-            self.num_samples,
-            0,
-        );
         let mut _velocity: f32 = 0.0;
         if let Some(rx_note_velocity) = &self.rx_note_velocity {
-            if let Ok(trigger_note_msg) = rx_note_velocity.try_recv() {
+            while let Ok(trigger_note_msg) = rx_note_velocity.try_recv() {
                 self.freq = trigger_note_msg.freq;
                 _velocity = trigger_note_msg.velocity;
-            };
-        };
-        //        let mut effect: Option<Box<dyn Effect>> = None;
-        let mut map: ParameterMap = HashMap::new();
-        if self.overdrive_toggle {
-            map.insert(
-                "overdrive".to_string(),
-                vec![
-                    format!("gain {}", self.overdrive).to_string(),
-                    format!("bypass {}", false).to_string(),
-                ],
-            );
-        } else {
-            map.insert(
-                "overdrive".to_string(),
-                vec![format!("bypass {}", true).to_string()],
-            );
-        }
-        let effect_params: Option<ParameterMap> = Some(map);
-        let msg = CtrlMsg {
-            size: 1024,
-            intensity_am: self.intensity_am,
-            freq_am: self.freq_am,
-            phase_am: self.phase_am,
-            intensity_fm: self.intensity_fm,
-            freq_fm: self.freq_fm,
-            phase_fm: self.phase_fm,
-            num_samples: self.num_samples,
-            volume: self.volume,
-            effect_params,
+                self.piano.set_sounding(
+                    freq_to_note(trigger_note_msg.freq),
+                    trigger_note_msg.note_type == NoteType::NoteOn,
+                );
+            }
         };
-        if let Some(ref x) = self.tx_ctrl {
-            let _ = x.send(msg);
+        let piano_events = self.piano.handle_keyboard(ctx);
+        self.send_piano_events(piano_events);
+        self.receive_midi_learned();
+        self.receive_file_changed();
+        self.receive_midi_monitor();
+        if self.rx_file_changed.is_some() {
+            // the watcher does not repaint
+            ctx.request_repaint_after(FILE_POLL_INTERVAL);
         }
-        let msg_adsr = Adsr {
-            ta: self.attack,
-            td: self.decay,
-            ts: self.sustain,
-            tr: self.release,
-        };
-        if let Some(x) = &self.tx_adsr {
-            let _ = x.send(msg_adsr);
+        if self.midi_learn_function.is_some() {
+            // nothing else repaints while waiting for the controller
+            ctx.request_repaint_after(Duration::from_millis(100));
         }
-        let (_, values_data) = my_sine.gen_values();
-
-        let wave_line = Line::new(PlotPoints::from_ys_f32(&values_data));
+        self.send_midi_feedback();
+        let eq_response_line = Line::new(eq_response_points(&self.equalizer_bands));
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Oscillator");
             ui.vertical(|ui| {
                 ui.horizontal(|ui| {
-                    ui.label("Num Samples: ");
-                    ui.add(egui::DragValue::new(&mut self.num_samples).speed(100.0));
+                    ui.label("Part: ");
+                    let mut selected_part = self.selected_part;
+                    for part in 0..self.part_configs.len() {
+                        ui.radio_value(&mut selected_part, part, format!("{}", part + 1));
+                    }
+                    self.select_part(selected_part);
+                    let config = &mut self.part_configs[self.selected_part];
+                    ui.label("Channel: ");
+                    egui::ComboBox::from_id_source("part_channel")
+                        .selected_text(config.channel.to_string())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut config.channel, MidiChannel::Omni, "Omni");
+                            ui.selectable_value(&mut config.channel, MidiChannel::Off, "Off");
+                            for channel in 0..16 {
+                                ui.selectable_value(
+                                    &mut config.channel,
+                                    MidiChannel::Channel(channel),
+                                    format!("{}", channel + 1),
+                                );
+                            }
+                        });
+                    ui.label("Level: ");
+                    ui.add(egui::Slider::new(&mut config.level, 0.0..=1.0));
+                    let mut separate = config.routing == PartRouting::Separate;
+                    if ui.checkbox(&mut separate, "separate out").changed() {
+                        config.routing = if separate {
+                            PartRouting::Separate
+                        } else {
+                            PartRouting::Mix
+                        };
+                    }
+                    ui.label(&self.patch_status);
                 });
                 ui.horizontal(|ui| {
                     ui.label("Freq Base: ");
@@ -209,42 +669,118 @@ impl eframe::App for OscillatorGui {
                 });
                 ui.horizontal(|ui| {
                     ui.label("Intensity AM: ");
-                    ui.add(egui::Slider::new(&mut self.intensity_am, 0.0..=1.0));
+                    let response = ui.add(egui::Slider::new(&mut self.intensity_am, 0.0..=1.0));
+                    self.midi_learn_menu(response, "intensity_am");
                     ui.label("Freq AM: ");
-                    ui.add(egui::Slider::new(&mut self.freq_am, 0.0..=10.0));
+                    let response = ui.add(egui::Slider::new(&mut self.freq_am, 0.0..=10.0));
+                    self.midi_learn_menu(response, "freq_am");
                 });
                 ui.horizontal(|ui| {
                     ui.label("Phase AM: ");
-                    ui.add(egui::Slider::new(
+                    let response = ui.add(egui::Slider::new(
                         &mut self.phase_am,
                         0.0..=std::f32::consts::TAU,
                     ));
+                    self.midi_learn_menu(response, "phase_am");
                 });
                 ui.horizontal(|ui| {
                     ui.label("Intensity FM: ");
-                    ui.add(egui::Slider::new(&mut self.intensity_fm, 0.0..=100.0));
+                    let response = ui.add(egui::Slider::new(&mut self.intensity_fm, 0.0..=100.0));
+                    self.midi_learn_menu(response, "intensity_fm");
                     ui.label("Freq FM: ");
-                    ui.add(egui::Slider::new(&mut self.freq_fm, 0.0..=10.0));
+                    let response = ui.add(egui::Slider::new(&mut self.freq_fm, 0.0..=10.0));
+                    self.midi_learn_menu(response, "freq_fm");
                 });
                 ui.horizontal(|ui| {
                     ui.label("Phase AM: ");
-                    ui.add(egui::Slider::new(
+                    let response = ui.add(egui::Slider::new(
                         &mut self.phase_fm,
                         0.0..=std::f32::consts::TAU,
                     ));
+                    self.midi_learn_menu(response, "phase_fm");
                 });
                 ui.horizontal(|ui| {
                     ui.label("Overdrive: ");
                     ui.add(status_button(&mut self.overdrive_toggle));
-                    ui.add(egui::Slider::new(&mut self.overdrive, 0.0..=10.0));
+                    let response = ui.add(egui::Slider::new(&mut self.overdrive, 0.0..=10.0));
+                    self.midi_learn_menu(response, "overdrive_gain");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pan: ");
+                    let response = ui.add(egui::Slider::new(&mut self.pan, -1.0..=1.0));
+                    self.midi_learn_menu(response, "pan");
+                    ui.label("Width: ");
+                    let response = ui.add(egui::Slider::new(&mut self.stereo_width, 0.0..=2.0));
+                    self.midi_learn_menu(response, "stereo_width");
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Pitch Bend: ");
+                    ui.add_enabled(false, egui::Slider::new(&mut self.pitch_bend, -1.0..=1.0));
+                    ui.label("Range: ");
+                    ui.add(egui::Slider::new(&mut self.pitch_bend_range, 0.0..=24.0).suffix(" st"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Vibrato (mod wheel/aftertouch) Rate: ");
+                    let response =
+                        ui.add(egui::Slider::new(&mut self.vibrato_rate, 0.0..=10.0).suffix(" Hz"));
+                    self.midi_learn_menu(response, "vibrato_rate");
+                    ui.label("Depth: ");
+                    let response =
+                        ui.add(egui::Slider::new(&mut self.vibrato_depth, 0.0..=2.0).suffix(" st"));
+                    self.midi_learn_menu(response, "vibrato_depth");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Pan Spread: ");
+                    let response = ui.add(egui::Slider::new(&mut self.pan_spread, 0.0..=1.0));
+                    self.midi_learn_menu(response, "pan_spread");
+                    ui.radio_value(&mut self.pan_mode, PanMode::NoteNumber, "Note");
+                    ui.radio_value(&mut self.pan_mode, PanMode::Random, "Random");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Auto Pan: ");
+                    ui.add(status_button(&mut self.autopan_toggle));
+                    ui.label("Rate: ");
+                    ui.add(egui::Slider::new(&mut self.autopan_rate, 0.0..=10.0).suffix(" Hz"));
+                    ui.label("Depth: ");
+                    ui.add(egui::Slider::new(&mut self.autopan_depth, 0.0..=1.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Equalizer: ");
+                    ui.add(status_button(&mut self.equalizer_toggle));
+                });
+                for band in self.equalizer_bands.iter_mut() {
+                    ui.horizontal(|ui| {
+                        let band_name = match band.band_type {
+                            BiquadType::LowShelf => "Low Shelf",
+                            BiquadType::HighShelf => "High Shelf",
+                            BiquadType::Peaking => "Peak",
+                        };
+                        ui.label(format!("{}: ", band_name));
+                        ui.add(
+                            egui::DragValue::new(&mut band.freq)
+                                .speed(10.0)
+                                .clamp_range(20.0..=20000.0)
+                                .suffix(" Hz"),
+                        );
+                        ui.label("Gain: ");
+                        ui.add(egui::Slider::new(&mut band.gain_db, -18.0..=18.0).suffix(" dB"));
+                        ui.label("Q: ");
+                        ui.add(egui::Slider::new(&mut band.q, 0.1..=10.0).logarithmic(true));
+                    });
+                }
 
                 ui.horizontal(|ui| {
-                    Plot::new("my_wave")
+                    let plot_width = 0.5 * ui.available_width();
+                    self.scope_view.show(ui, plot_width);
+                    Plot::new("eq_response")
+                        .width(plot_width)
                         .view_aspect(2.0)
-                        .data_aspect(self.num_samples as f32 / 4.0)
-                        .show(ui, |plot_ui| plot_ui.line(wave_line));
+                        .include_y(-18.0)
+                        .include_y(18.0)
+                        .x_axis_formatter(|mark, _, _| format!("{:.0} Hz", 10f64.powf(mark.value)))
+                        .show(ui, |plot_ui| plot_ui.line(eq_response_line));
                 });
+                self.spectrum_view.show(ui);
                 ui.horizontal(|ui| {
                     ui.label("Attack: ");
                     ui.add(egui::Slider::new(&mut self.attack, 0.0..=1.0));
@@ -277,7 +813,7 @@ impl eframe::App for OscillatorGui {
                                     velocity: self.velocity,
                                     length: self.length,
                                 };
-                                if let Err(e) = x.send(trigger_note) {
+                                if let Err(e) = x.send((self.selected_part, trigger_note)) {
                                     println!("could send trigger_note e: {}", e);
                                 };
                             }
@@ -289,7 +825,7 @@ impl eframe::App for OscillatorGui {
                                     velocity: self.velocity,
                                     length: self.length,
                                 };
-                                if let Err(e) = x.send(trigger_note_off) {
+                                if let Err(e) = x.send((self.selected_part, trigger_note_off)) {
                                     println!("could send trigger_note_off e: {}", e);
                                 };
                             }
@@ -315,9 +851,104 @@ impl eframe::App for OscillatorGui {
                             ctx.send_viewport_cmd(ViewportCommand::Close)
                         };
                     }
-                })
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Piano octave (z/x): ");
+                    ui.add(egui::DragValue::new(&mut self.piano.octave).clamp_range(0..=9));
+                    ui.label("Velocity (c/v): ");
+                    ui.add(egui::Slider::new(&mut self.piano.velocity, 0.1..=1.0));
+                });
+                let piano_events = self.piano.show(ui);
+                self.send_piano_events(piano_events);
+                ui.horizontal(|ui| {
+                    ui.label("Record: ");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.record_file_path).desired_width(150.0),
+                    );
+                    ui.radio_value(
+                        &mut self.record_sample_format,
+                        SampleFormat::Int16,
+                        "16 bit",
+                    );
+                    ui.radio_value(
+                        &mut self.record_sample_format,
+                        SampleFormat::Int24,
+                        "24 bit",
+                    );
+                    ui.radio_value(
+                        &mut self.record_sample_format,
+                        SampleFormat::Float32,
+                        "float",
+                    );
+                    if let Some(ref mut recorder) = self.recorder {
+                        if recorder.is_recording() {
+                            if ui.button("stop").clicked() {
+                                self.record_status = match recorder.stop() {
+                                    Ok(num_frames) => format!("wrote {} frames", num_frames),
+                                    Err(e) => format!("record error: {}", e),
+                                };
+                            }
+                        } else if ui.button("start").clicked() {
+                            self.record_status = match recorder
+                                .start(&self.record_file_path, self.record_sample_format)
+                            {
+                                Ok(()) => "recording".to_string(),
+                                Err(e) => format!("record error: {}", e),
+                            };
+                        }
+                    }
+                    ui.label(&self.record_status);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("MIDI mapping: ");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.midi_mapping_file_path)
+                            .desired_width(150.0),
+                    );
+                    if ui.button("save").clicked() {
+                        self.midi_mapping_status =
+                            match write_midi_functions_with_midi_msgs_advanced_to_json_file(
+                                &self.midi_functions,
+                                &self.midi_mapping_file_path,
+                            ) {
+                                Ok(()) => format!("saved {}", self.midi_mapping_file_path),
+                                Err(e) => format!("mapping error: {}", e),
+                            };
+                    }
+                    if let Some(function) = self.midi_learn_function.clone() {
+                        ui.label(format!("move a controller for {}", function));
+                        if ui.button("cancel").clicked() {
+                            self.send_midi_learn_cmd(MidiLearnCmd::Cancel);
+                            self.midi_learn_function = None;
+                        }
+                    } else {
+                        ui.label(&self.midi_mapping_status);
+                    }
+                    ui.toggle_value(&mut self.midi_monitor_open, "MIDI monitor");
+                });
+                if let Some(ref trigger_backlog) = self.trigger_backlog {
+                    let backlog = trigger_backlog.load(Ordering::Relaxed);
+                    self.max_trigger_backlog = self.max_trigger_backlog.max(backlog);
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Trigger backlog: {} (max {})",
+                            backlog, self.max_trigger_backlog
+                        ));
+                        if ui.button("reset").clicked() {
+                            self.max_trigger_backlog = 0;
+                        }
+                    });
+                }
             });
         });
+        self.part_patches[self.selected_part] = self.get_patch();
+        self.send_part_events();
+        self.show_midi_monitor(ctx);
+        self.midi_monitor.set_open(self.midi_monitor_open);
+        if self.midi_monitor.is_receiving() {
+            // incoming messages do not repaint
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
     }
     fn on_exit(&mut self, _gl: Option<&Context>) {
         if let Some(ref mut tx_close) = self.tx_close {
@@ -333,6 +964,24 @@ impl eframe::App for OscillatorGui {
     }
 }
 
+/// combined response of the eq bands in dB over a logarithmic frequency axis (log10(freq), dB)
+fn eq_response_points(bands: &[EqBand]) -> PlotPoints {
+    let mut equalizer = Equalizer::new();
+    for (index, band) in bands.iter().enumerate() {
+        equalizer.set_band(index, *band);
+    }
+    let num_points = 256;
+    let (log_freq_min, log_freq_max) = (20f64.log10(), 20000f64.log10());
+    (0..num_points)
+        .map(|i| {
+            let log_freq =
+                log_freq_min + (log_freq_max - log_freq_min) * i as f64 / (num_points - 1) as f64;
+            let freq = 10f64.powf(log_freq);
+            [log_freq, equalizer.magnitude_db(freq as f32) as f64]
+        })
+        .collect()
+}
+
 fn repainter<MsgType>(
     ctx: egui::Context,
     rx_msg: Option<Receiver<MsgType>>,
@@ -0,0 +1,302 @@
+use eframe::egui::{self, Key};
+use std::collections::{BTreeSet, HashMap};
+
+/// octaves shown by the on-screen keyboard
+const NUM_OCTAVES: u8 = 4;
+/// semitones of the white keys in an octave
+const WHITE_KEYS: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+/// semitones of the black keys and the white key they follow
+const BLACK_KEYS: [(u8, usize); 5] = [(1, 0), (3, 1), (6, 3), (8, 4), (10, 5)];
+/// computer keyboard keys of the notes from the first C on, two rows like a piano
+const NOTE_KEYS: [Key; 17] = [
+    Key::A,
+    Key::W,
+    Key::S,
+    Key::E,
+    Key::D,
+    Key::F,
+    Key::T,
+    Key::G,
+    Key::Y,
+    Key::H,
+    Key::U,
+    Key::J,
+    Key::K,
+    Key::O,
+    Key::L,
+    Key::P,
+    Key::Semicolon,
+];
+
+/// Note on (true) or note off of a midi note.
+pub type PianoEvent = (u8, bool);
+
+/// On-screen keyboard and computer keyboard note input.
+/// z/x shift the octave, c/v change the velocity.
+pub struct Piano {
+    /// octave of the a key, 4 is middle C
+    pub octave: u8,
+    pub velocity: f32,
+    /// notes sounding from the piano and from midi in, shown highlighted
+    sounding: BTreeSet<u8>,
+    mouse_note: Option<u8>,
+    /// note started by each pressed key, an octave shift does not change it
+    key_notes: HashMap<Key, u8>,
+}
+
+impl Default for Piano {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Piano {
+    pub fn new() -> Self {
+        Piano {
+            octave: 4,
+            velocity: 0.8,
+            sounding: BTreeSet::new(),
+            mouse_note: None,
+            key_notes: HashMap::new(),
+        }
+    }
+
+    /// notes played by other sources, for the display
+    pub fn set_sounding(&mut self, note: u8, on: bool) {
+        if on {
+            self.sounding.insert(note);
+        } else {
+            self.sounding.remove(&note);
+        }
+    }
+
+    fn first_note(&self) -> u8 {
+        // the keyboard starts an octave below the a key
+        12 * self.octave.min(10 - NUM_OCTAVES)
+    }
+
+    fn event(&mut self, events: &mut Vec<PianoEvent>, note: u8, on: bool) {
+        self.set_sounding(note, on);
+        events.push((note, on));
+    }
+
+    /// note offs of the held keys and the clicked key
+    fn release_all(&mut self, events: &mut Vec<PianoEvent>) {
+        let notes: Vec<u8> = self
+            .key_notes
+            .drain()
+            .map(|(_, note)| note)
+            .chain(self.mouse_note.take())
+            .collect();
+        for note in notes {
+            self.event(events, note, false);
+        }
+    }
+
+    /// note events of the computer keyboard, nothing while a text field has the focus,
+    /// held notes stop when the window loses the focus as their key releases would be missed
+    pub fn handle_keyboard(&mut self, ctx: &egui::Context) -> Vec<PianoEvent> {
+        let mut events = Vec::new();
+        if !ctx.input(|input| input.focused) {
+            self.release_all(&mut events);
+            return events;
+        }
+        if ctx.wants_keyboard_input() {
+            return events;
+        }
+        let key_events: Vec<(Key, bool)> = ctx.input(|input| {
+            input
+                .events
+                .iter()
+                .filter_map(|event| match *event {
+                    egui::Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        ..
+                    } => Some((key, pressed)),
+                    _ => None,
+                })
+                .collect()
+        });
+        for (key, pressed) in key_events {
+            match key {
+                Key::Z if pressed => self.octave = self.octave.saturating_sub(1),
+                Key::X if pressed => self.octave = (self.octave + 1).min(9),
+                Key::C if pressed => self.velocity = (self.velocity - 0.1).max(0.1),
+                Key::V if pressed => self.velocity = (self.velocity + 0.1).min(1.0),
+                _ => {
+                    let Some(offset) = NOTE_KEYS.iter().position(|note_key| *note_key == key)
+                    else {
+                        continue;
+                    };
+                    if pressed {
+                        let note = 12 * (self.octave + 1) + offset as u8;
+                        if note <= 127 && !self.key_notes.contains_key(&key) {
+                            self.key_notes.insert(key, note);
+                            self.event(&mut events, note, true);
+                        }
+                    } else if let Some(note) = self.key_notes.remove(&key) {
+                        self.event(&mut events, note, false);
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// draw the keys, clicking and dragging over them plays notes
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Vec<PianoEvent> {
+        let mut events = Vec::new();
+        let num_white_keys = 7 * NUM_OCTAVES as usize + 1;
+        let desired_size = egui::vec2(ui.available_width(), 80.0);
+        let (rect, response) = ui.allocate_exact_size(desired_size, egui::Sense::drag());
+        let white_width = rect.width() / num_white_keys as f32;
+        let black_size = egui::vec2(0.6 * white_width, 0.6 * rect.height());
+        let first_note = self.first_note();
+        let white_rect = |index: usize| {
+            egui::Rect::from_min_size(
+                rect.min + egui::vec2(index as f32 * white_width, 0.0),
+                egui::vec2(white_width, rect.height()),
+            )
+        };
+        let white_keys: Vec<(u8, egui::Rect)> = (0..num_white_keys)
+            .map(|index| {
+                let note = first_note + 12 * (index / 7) as u8 + WHITE_KEYS[index % 7];
+                (note, white_rect(index))
+            })
+            .collect();
+        let black_keys: Vec<(u8, egui::Rect)> = (0..NUM_OCTAVES as usize)
+            .flat_map(|octave| {
+                BLACK_KEYS.iter().map(move |(semitone, white_index)| {
+                    let right = white_rect(7 * octave + white_index).right();
+                    (
+                        first_note + 12 * octave as u8 + semitone,
+                        egui::Rect::from_min_size(
+                            egui::pos2(right - 0.5 * black_size.x, rect.top()),
+                            black_size,
+                        ),
+                    )
+                })
+            })
+            .collect();
+
+        // black keys lie on top of the white keys
+        let pointer_note = response.interact_pointer_pos().and_then(|pos| {
+            black_keys
+                .iter()
+                .chain(white_keys.iter())
+                .find(|(_, key_rect)| key_rect.contains(pos))
+                .map(|(note, _)| *note)
+        });
+        if pointer_note != self.mouse_note {
+            if let Some(note) = self.mouse_note.take() {
+                self.event(&mut events, note, false);
+            }
+            if let Some(note) = pointer_note {
+                self.event(&mut events, note, true);
+                self.mouse_note = Some(note);
+            }
+        }
+
+        if ui.is_rect_visible(rect) {
+            let painter = ui.painter();
+            let visuals = ui.visuals();
+            let stroke = egui::Stroke::new(1.0, egui::Color32::DARK_GRAY);
+            for (note, key_rect) in white_keys.iter() {
+                let fill = if self.sounding.contains(note) {
+                    visuals.selection.bg_fill
+                } else {
+                    egui::Color32::WHITE
+                };
+                painter.rect(*key_rect, 2.0, fill, stroke);
+            }
+            for (note, key_rect) in black_keys.iter() {
+                let fill = if self.sounding.contains(note) {
+                    visuals.selection.bg_fill
+                } else {
+                    egui::Color32::BLACK
+                };
+                painter.rect(*key_rect, 2.0, fill, stroke);
+            }
+            // mark the C the a key plays
+            let keyboard_c = 12 * (self.octave as usize + 1);
+            if let Some((_, key_rect)) = white_keys
+                .iter()
+                .find(|(note, _)| *note as usize == keyboard_c)
+            {
+                painter.circle_filled(
+                    egui::pos2(key_rect.center().x, key_rect.bottom() - 8.0),
+                    3.0,
+                    egui::Color32::GRAY,
+                );
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_event(key: Key, pressed: bool) -> egui::Event {
+        egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed,
+            repeat: false,
+            modifiers: egui::Modifiers::NONE,
+        }
+    }
+
+    fn run_frame(
+        piano: &mut Piano,
+        ctx: &egui::Context,
+        focused: bool,
+        events: Vec<egui::Event>,
+    ) -> Vec<PianoEvent> {
+        let raw_input = egui::RawInput {
+            focused,
+            events,
+            ..Default::default()
+        };
+        let mut piano_events = Vec::new();
+        let _ = ctx.run(raw_input, |ctx| piano_events = piano.handle_keyboard(ctx));
+        piano_events
+    }
+
+    #[test]
+    fn key_plays_note_until_released() {
+        let ctx = egui::Context::default();
+        let mut piano = Piano::new();
+        let events = run_frame(&mut piano, &ctx, true, vec![key_event(Key::A, true)]);
+        assert_eq!(events, vec![(60, true)]);
+        // the octave shift does not change the note of the held key
+        let events = run_frame(
+            &mut piano,
+            &ctx,
+            true,
+            vec![key_event(Key::X, true), key_event(Key::A, false)],
+        );
+        assert_eq!(events, vec![(60, false)]);
+    }
+
+    #[test]
+    fn focus_loss_releases_held_notes() {
+        let ctx = egui::Context::default();
+        let mut piano = Piano::new();
+        run_frame(
+            &mut piano,
+            &ctx,
+            true,
+            vec![key_event(Key::A, true), key_event(Key::D, true)],
+        );
+        let mut events = run_frame(&mut piano, &ctx, false, Vec::new());
+        events.sort();
+        assert_eq!(events, vec![(60, false), (64, false)]);
+        assert!(run_frame(&mut piano, &ctx, false, Vec::new()).is_empty());
+        // the key release arriving after the focus returns sends nothing
+        assert!(run_frame(&mut piano, &ctx, true, vec![key_event(Key::A, false)]).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use clap::{Parser, ValueEnum};
+use oscillator_lib::engine::{Engine, EngineEvent};
+use oscillator_lib::midi_player::MidiPlayer;
+use oscillator_lib::patch::{parse_json_file_to_patch, Patch};
+use oscillator_lib::smf::Smf;
+use oscillator_lib::wav::{write_wav_file, SampleFormat, WavSpec};
+use std::process::exit;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum WavFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+/// render a standard midi file offline into a wav file
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// midi file to render (format 0 or 1)
+    input: String,
+    /// wav file to write
+    output: String,
+    /// patch json file with the sound parameters
+    #[arg(short, long, value_name = "filepath")]
+    patch: Option<String>,
+    /// sample rate of the rendered wav file
+    #[arg(short, long, default_value_t = 48000)]
+    sample_rate: u32,
+    /// sample format of the rendered wav file
+    #[arg(short, long, value_enum, default_value_t = WavFormat::Int24)]
+    format: WavFormat,
+}
+
+fn main() {
+    let args = Args::parse();
+    let patch = match args.patch {
+        Some(ref patch_filepath) => parse_json_file_to_patch(patch_filepath).unwrap_or_else(|e| {
+            println!("could not load patch e: {}", e);
+            exit(-1);
+        }),
+        None => Patch::default(),
+    };
+    let smf = Smf::from_file(&args.input).unwrap_or_else(|e| {
+        println!("could not load midi file e: {}", e);
+        exit(-1);
+    });
+    let midi_player = MidiPlayer::from_smf(&smf);
+
+    let frame_size: usize = 1024;
+    let sample_rate = args.sample_rate as f32;
+    let mut engine = Engine::new(sample_rate, frame_size);
+    engine.schedule(0, EngineEvent::Adsr(patch.to_adsr()));
+    engine.schedule(0, EngineEvent::Ctrl(patch.to_ctrl_msg()));
+    engine.schedule_events(midi_player.to_scheduled_events(sample_rate, patch.length));
+
+    // let the release of the last notes ring out
+    let release_frames = (patch.release * patch.length as f32) as usize + frame_size;
+    let num_frames = (midi_player.duration() * sample_rate as f64) as usize + release_frames;
+    let (out_l, out_r) = engine.render(num_frames);
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: args.sample_rate,
+        sample_format: match args.format {
+            WavFormat::Int16 => SampleFormat::Int16,
+            WavFormat::Int24 => SampleFormat::Int24,
+            WavFormat::Float32 => SampleFormat::Float32,
+        },
+    };
+    match write_wav_file(&args.output, spec, &[&out_l, &out_r]) {
+        Ok(()) => println!(
+            "rendered {:.2}s of {} into {}",
+            num_frames as f64 / sample_rate as f64,
+            args.input,
+            args.output
+        ),
+        Err(e) => {
+            println!("could not write wav file e: {}", e);
+            exit(-1);
+        }
+    }
+}
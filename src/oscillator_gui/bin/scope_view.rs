@@ -0,0 +1,125 @@
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use oscillator_lib::scope::{find_last_trigger, Scope, SCOPE_RING_FRAMES};
+use std::time::Duration;
+
+/// horizontal and vertical divisions of the screen
+const DIVS_X: f32 = 10.0;
+const DIVS_Y: f32 = 8.0;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Triggered oscilloscope of the jack output.
+pub struct ScopeView {
+    scope: Option<Scope>,
+    /// keep the last picture
+    pub hold: bool,
+    pub ms_per_div: f32,
+    pub amplitude_per_div: f32,
+    /// rising crossing of the left channel that starts the picture, free running without crossing
+    pub trigger_level: f32,
+    samples_l: Vec<f32>,
+    samples_r: Vec<f32>,
+    /// picture starting at the trigger, in ms and amplitude
+    points_l: Vec<[f64; 2]>,
+    points_r: Vec<[f64; 2]>,
+    triggered: bool,
+}
+
+impl ScopeView {
+    pub fn new(scope: Option<Scope>) -> Self {
+        ScopeView {
+            scope,
+            hold: false,
+            ms_per_div: 1.0,
+            amplitude_per_div: 0.25,
+            trigger_level: 0.0,
+            samples_l: Vec::with_capacity(SCOPE_RING_FRAMES / 2),
+            samples_r: Vec::with_capacity(SCOPE_RING_FRAMES / 2),
+            points_l: Vec::new(),
+            points_r: Vec::new(),
+            triggered: false,
+        }
+    }
+
+    fn update_points(&mut self) {
+        let Some(ref scope) = self.scope else {
+            return;
+        };
+        let sample_rate = scope.get_sample_rate() as f32;
+        let window_frames = ((self.ms_per_div * DIVS_X * 0.001 * sample_rate) as usize).max(2);
+        // room to find a crossing one window before the latest frames
+        scope.get_latest(2 * window_frames, &mut self.samples_l, &mut self.samples_r);
+        if self.samples_l.len() < window_frames {
+            return;
+        }
+        let trigger = find_last_trigger(&self.samples_l, self.trigger_level, window_frames);
+        self.triggered = trigger.is_some();
+        let start = trigger.unwrap_or(self.samples_l.len() - window_frames);
+        let max_amplitude = (self.amplitude_per_div * DIVS_Y / 2.0) as f64;
+        let to_points = |samples: &[f32]| -> Vec<[f64; 2]> {
+            samples[start..start + window_frames]
+                .iter()
+                .enumerate()
+                .map(|(frame, sample)| {
+                    [
+                        1000.0 * frame as f64 / sample_rate as f64,
+                        (*sample as f64).clamp(-max_amplitude, max_amplitude),
+                    ]
+                })
+                .collect()
+        };
+        self.points_l = to_points(&self.samples_l);
+        self.points_r = to_points(&self.samples_r);
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, width: f32) {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut self.hold, "hold");
+                ui.label("ms/div: ");
+                ui.add(
+                    egui::DragValue::new(&mut self.ms_per_div)
+                        .speed(0.05)
+                        .clamp_range(0.05..=30.0),
+                );
+                ui.label("amp/div: ");
+                ui.add(
+                    egui::DragValue::new(&mut self.amplitude_per_div)
+                        .speed(0.01)
+                        .clamp_range(0.01..=1.0),
+                );
+                ui.label("trigger: ");
+                ui.add(
+                    egui::DragValue::new(&mut self.trigger_level)
+                        .speed(0.01)
+                        .clamp_range(-1.0..=1.0),
+                );
+                ui.label(if self.triggered { "trig'd" } else { "auto" });
+            });
+            // nothing to show without jack output
+            if !self.hold && self.scope.is_some() {
+                self.update_points();
+                ui.ctx().request_repaint_after(REFRESH_INTERVAL);
+            }
+            let max_amplitude = (self.amplitude_per_div * DIVS_Y / 2.0) as f64;
+            let line_l = Line::new(PlotPoints::from(self.points_l.clone())).name("L");
+            let line_r = Line::new(PlotPoints::from(self.points_r.clone())).name("R");
+            Plot::new("scope")
+                .width(width)
+                .view_aspect(2.0)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .include_x(0.0)
+                .include_x((self.ms_per_div * DIVS_X) as f64)
+                .include_y(-max_amplitude)
+                .include_y(max_amplitude)
+                .x_axis_formatter(|mark, _, _| format!("{:.1} ms", mark.value))
+                .legend(Legend::default())
+                .show(ui, |plot_ui| {
+                    plot_ui.line(line_l);
+                    plot_ui.line(line_r);
+                });
+        });
+    }
+}
@@ -0,0 +1,143 @@
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use oscillator_lib::scope::Scope;
+use oscillator_lib::spectrum::{SpectrumAnalyzer, SpectrumWindow, SPECTRUM_MIN_DB};
+use std::time::Duration;
+
+const FFT_SIZES: [usize; 5] = [1024, 2048, 4096, 8192, 16384];
+const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+/// lowest frequency on the log axis
+const MIN_FREQ: f32 = 20.0;
+
+/// Spectrum of the jack output, read from the same ring buffer as the scope.
+pub struct SpectrumView {
+    scope: Option<Scope>,
+    analyzer: SpectrumAnalyzer,
+    pub hold: bool,
+    pub peak_hold: bool,
+    samples_l: Vec<f32>,
+    samples_r: Vec<f32>,
+    mono: Vec<f32>,
+}
+
+impl SpectrumView {
+    pub fn new(scope: Option<Scope>) -> Self {
+        SpectrumView {
+            scope,
+            analyzer: SpectrumAnalyzer::new(4096, SpectrumWindow::BlackmanHarris),
+            hold: false,
+            peak_hold: false,
+            samples_l: Vec::new(),
+            samples_r: Vec::new(),
+            mono: Vec::new(),
+        }
+    }
+
+    fn update(&mut self) {
+        let Some(ref scope) = self.scope else {
+            return;
+        };
+        scope.get_latest(
+            self.analyzer.get_size(),
+            &mut self.samples_l,
+            &mut self.samples_r,
+        );
+        if self.samples_l.is_empty() {
+            return;
+        }
+        self.mono.clear();
+        self.mono.extend(
+            self.samples_l
+                .iter()
+                .zip(self.samples_r.iter())
+                .map(|(sample_l, sample_r)| 0.5 * (sample_l + sample_r)),
+        );
+        self.analyzer.process(&self.mono);
+    }
+
+    /// (log10(freq), dB) of the bins above MIN_FREQ
+    fn to_points(&self, magnitudes_db: &[f32], sample_rate: f32) -> PlotPoints {
+        magnitudes_db
+            .iter()
+            .enumerate()
+            .filter_map(|(bin, db)| {
+                let freq = self.analyzer.get_bin_freq(bin, sample_rate);
+                (freq >= MIN_FREQ).then_some([freq.log10() as f64, *db as f64])
+            })
+            .collect()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label("Spectrum: ");
+                ui.toggle_value(&mut self.hold, "hold");
+                let mut size = self.analyzer.get_size();
+                egui::ComboBox::from_id_source("spectrum_size")
+                    .selected_text(format!("{} points", size))
+                    .show_ui(ui, |ui| {
+                        for fft_size in FFT_SIZES {
+                            ui.selectable_value(&mut size, fft_size, format!("{}", fft_size));
+                        }
+                    });
+                if size != self.analyzer.get_size() {
+                    self.analyzer.set_size(size);
+                }
+                let mut window = self.analyzer.get_window();
+                egui::ComboBox::from_id_source("spectrum_window")
+                    .selected_text(window.to_string())
+                    .show_ui(ui, |ui| {
+                        for spectrum_window in SpectrumWindow::ALL {
+                            ui.selectable_value(
+                                &mut window,
+                                spectrum_window,
+                                spectrum_window.to_string(),
+                            );
+                        }
+                    });
+                if window != self.analyzer.get_window() {
+                    self.analyzer.set_window(window);
+                }
+                ui.label("Averaging: ");
+                ui.add(egui::Slider::new(&mut self.analyzer.averaging, 0.0..=0.95));
+                ui.checkbox(&mut self.peak_hold, "peak hold");
+                if ui.button("reset").clicked() {
+                    self.analyzer.reset_peaks();
+                }
+            });
+            // nothing to show without jack output
+            if !self.hold && self.scope.is_some() {
+                self.update();
+                ui.ctx().request_repaint_after(REFRESH_INTERVAL);
+            }
+            let sample_rate = self
+                .scope
+                .as_ref()
+                .map_or(48000.0, |scope| scope.get_sample_rate() as f32);
+            let magnitude_line =
+                Line::new(self.to_points(self.analyzer.get_magnitudes_db(), sample_rate))
+                    .name("level");
+            let peak_line = self.peak_hold.then(|| {
+                Line::new(self.to_points(self.analyzer.get_peaks_db(), sample_rate)).name("peak")
+            });
+            Plot::new("spectrum")
+                .view_aspect(4.0)
+                .allow_drag(false)
+                .allow_zoom(false)
+                .allow_scroll(false)
+                .include_x(MIN_FREQ.log10() as f64)
+                .include_x((0.5 * sample_rate).log10() as f64)
+                .include_y(SPECTRUM_MIN_DB as f64)
+                .include_y(0.0)
+                .x_axis_formatter(|mark, _, _| format!("{:.0} Hz", 10f64.powf(mark.value)))
+                .y_axis_formatter(|mark, _, _| format!("{:.0} dB", mark.value))
+                .legend(Legend::default())
+                .show(ui, |plot_ui| {
+                    plot_ui.line(magnitude_line);
+                    if let Some(peak_line) = peak_line {
+                        plot_ui.line(peak_line);
+                    }
+                });
+        });
+    }
+}
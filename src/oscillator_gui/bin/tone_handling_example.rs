@@ -1,5 +1,6 @@
 use oscillator_lib::adsr::Adsr;
 use oscillator_lib::ctrl_msg::CtrlMsg;
+use oscillator_lib::stereo::PanMode;
 use oscillator_lib::tone_handling::ToneHandling;
 use oscillator_lib::trigger_note_msg::{NoteType, TriggerNoteMsg};
 
@@ -25,8 +26,14 @@ fn main() {
         intensity_fm: 0.0,
         freq_fm: 0.0,
         phase_fm: 0.0,
-        num_samples: 96000,
         volume: 1.0,
+        pan: 0.0,
+        pan_spread: 0.0,
+        pan_mode: PanMode::NoteNumber,
+        stereo_width: 1.0,
+        pitch_bend_range: 2.0,
+        vibrato_rate: 5.0,
+        vibrato_depth: 0.5,
         effect_params: None,
     };
 
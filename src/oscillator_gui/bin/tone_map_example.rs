@@ -14,6 +14,7 @@ fn main() {
         note_type: NoteType::NoteOn,
         freq: 440.0,
         velocity: 0.9,
+        pan_random: 0.0,
         start_pose: 0,
         adsr_envelope: Adsr::new(0.1, 0.2, 0.5, 0.2),
         envelope: None,
@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+
+use crate::adsr::Adsr;
+use crate::autopan::AutoPan;
+use crate::ctrl_msg::CtrlMsg;
+use crate::effect::Effect;
+use crate::engine::EngineEvent;
+use crate::equalizer::Equalizer;
+use crate::overdrive::Overdrive;
+use crate::pedal::PedalState;
+use crate::stereo::{apply_stereo_width, PanMode};
+use crate::tone_handling::ToneHandling;
+use crate::trigger_note_msg::NoteType;
+
+/// time constant to glide to a new pitch bend value
+const PITCH_BEND_SMOOTH_TIME: f32 = 0.005;
+/// block size while the pitch bend glides or the vibrato is active
+pub const PITCH_BEND_SMOOTH_FRAMES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiChannel {
+    /// ignores midi, plays only triggers sent to the part
+    Off,
+    Omni,
+    /// 0..=15, shown as 1..=16
+    Channel(u8),
+}
+
+impl MidiChannel {
+    pub fn accepts(&self, channel: u8) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Omni => true,
+            Self::Channel(part_channel) => *part_channel == channel & 0x0f,
+        }
+    }
+}
+
+impl std::fmt::Display for MidiChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "Off"),
+            Self::Omni => write!(f, "Omni"),
+            Self::Channel(channel) => write!(f, "{}", channel + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PartRouting {
+    /// mixed into the main output
+    Mix,
+    /// own output, if the backend provides one
+    Separate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PartConfig {
+    pub channel: MidiChannel,
+    pub level: f32,
+    pub routing: PartRouting,
+}
+
+impl Default for PartConfig {
+    fn default() -> Self {
+        PartConfig {
+            channel: MidiChannel::Omni,
+            level: 1.0,
+            routing: PartRouting::Mix,
+        }
+    }
+}
+
+/// One timbre of the engine: its own voices, pedals, controllers, patch and effect chain.
+pub struct Part {
+    config: PartConfig,
+    tone_handling: ToneHandling,
+    effect_chain: Vec<Box<dyn Effect>>,
+    ctrl_msg: CtrlMsg,
+    adsr_envelope: Adsr,
+    pitch_bend: f32,
+    pitch_bend_current: f32,
+    pedal_state: PedalState,
+    mod_wheel: f32,
+    aftertouch: f32,
+    vibrato_phase: f32,
+    sample_rate: f32,
+    max_frame_size: usize,
+    multiply_out_l: Vec<f32>,
+    multiply_out_r: Vec<f32>,
+    effect_in_l: Vec<f32>,
+    effect_in_r: Vec<f32>,
+    effect_out_l: Vec<f32>,
+    effect_out_r: Vec<f32>,
+}
+
+impl Part {
+    /// part with the default effect chain: overdrive, equalizer, autopan
+    pub fn new(sample_rate: f32, max_frame_size: usize) -> Self {
+        let mut effect_chain: Vec<Box<dyn Effect>> = Vec::new();
+        let mut overdrive = Overdrive::new();
+        overdrive.set_gain(1.0);
+        effect_chain.push(Box::new(overdrive));
+        let mut equalizer = Equalizer::new();
+        equalizer.set_sample_rate(sample_rate);
+        effect_chain.push(Box::new(equalizer));
+        let mut autopan = AutoPan::new();
+        autopan.set_sample_rate(sample_rate);
+        autopan.bypassing = true;
+        effect_chain.push(Box::new(autopan));
+        Part::with_effect_chain(sample_rate, max_frame_size, effect_chain)
+    }
+
+    pub fn with_effect_chain(
+        sample_rate: f32,
+        max_frame_size: usize,
+        effect_chain: Vec<Box<dyn Effect>>,
+    ) -> Self {
+        let mut tone_handling = ToneHandling::new();
+        tone_handling.set_sample_rate(sample_rate);
+        Part {
+            config: PartConfig::default(),
+            tone_handling,
+            effect_chain,
+            ctrl_msg: CtrlMsg {
+                size: 0,
+                intensity_am: 0.0,
+                freq_am: 0.0,
+                phase_am: 0.0,
+                intensity_fm: 0.0,
+                freq_fm: 0.0,
+                phase_fm: 0.0,
+                volume: 1.0,
+                pan: 0.0,
+                pan_spread: 0.0,
+                pan_mode: PanMode::NoteNumber,
+                stereo_width: 1.0,
+                pitch_bend_range: 2.0,
+                vibrato_rate: 5.0,
+                vibrato_depth: 0.5,
+                effect_params: None,
+            },
+            adsr_envelope: Adsr::new(0.1, 0.2, 0.5, 0.2),
+            pitch_bend: 0.0,
+            pitch_bend_current: 0.0,
+            pedal_state: PedalState::new(),
+            mod_wheel: 0.0,
+            aftertouch: 0.0,
+            vibrato_phase: 0.0,
+            sample_rate,
+            max_frame_size,
+            multiply_out_l: vec![1.0; max_frame_size],
+            multiply_out_r: vec![1.0; max_frame_size],
+            effect_in_l: vec![0.0; max_frame_size],
+            effect_in_r: vec![0.0; max_frame_size],
+            effect_out_l: vec![0.0; max_frame_size],
+            effect_out_r: vec![0.0; max_frame_size],
+        }
+    }
+
+    pub fn get_config(&self) -> PartConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: PartConfig) {
+        self.config = config;
+    }
+
+    /// current pitch bend value, -1.0..=1.0
+    pub fn get_pitch_bend(&self) -> f32 {
+        self.pitch_bend
+    }
+
+    /// semitones up and down at full pitch bend
+    pub fn get_pitch_bend_range(&self) -> f32 {
+        self.ctrl_msg.pitch_bend_range
+    }
+
+    pub fn get_pedal_state(&self) -> &PedalState {
+        &self.pedal_state
+    }
+
+    /// pitch bend glides or vibrato runs, rendering needs short blocks
+    pub fn is_modulating(&self) -> bool {
+        self.pitch_bend_current != self.pitch_bend || self.get_vibrato_depth() > 0.0
+    }
+
+    pub fn handle_event(&mut self, event: EngineEvent) {
+        match event {
+            EngineEvent::Trigger(trigger_msg) => {
+                let trigger_msg = match trigger_msg.note_type {
+                    NoteType::NoteOn => Some(self.pedal_state.note_on(trigger_msg)),
+                    NoteType::NoteOff => self.pedal_state.note_off(trigger_msg),
+                };
+                if let Some(trigger_msg) = trigger_msg {
+                    self.tone_handling.add_note_msg(
+                        trigger_msg,
+                        self.adsr_envelope.clone(),
+                        self.max_frame_size,
+                    );
+                }
+            }
+            EngineEvent::Ctrl(ctrl_msg) => {
+                if let Some(ref effect_params) = ctrl_msg.effect_params {
+                    for effect in self.effect_chain.iter_mut() {
+                        effect.set_params(effect_params);
+                    }
+                }
+                self.ctrl_msg = ctrl_msg;
+            }
+            EngineEvent::Adsr(adsr_envelope) => self.adsr_envelope = adsr_envelope,
+            EngineEvent::PitchBend(pitch_bend) => self.pitch_bend = pitch_bend.clamp(-1.0, 1.0),
+            EngineEvent::PitchBendRange(pitch_bend_range) => {
+                self.ctrl_msg.pitch_bend_range = pitch_bend_range
+            }
+            EngineEvent::Sustain(sustain) => {
+                let (tone_handling, adsr_envelope) = (&mut self.tone_handling, &self.adsr_envelope);
+                let max_frame_size = self.max_frame_size;
+                self.pedal_state.set_sustain(sustain, |note_off| {
+                    tone_handling.add_note_msg(note_off, adsr_envelope.clone(), max_frame_size)
+                });
+            }
+            EngineEvent::Sostenuto(sostenuto) => {
+                let (tone_handling, adsr_envelope) = (&mut self.tone_handling, &self.adsr_envelope);
+                let max_frame_size = self.max_frame_size;
+                self.pedal_state.set_sostenuto(sostenuto, |note_off| {
+                    tone_handling.add_note_msg(note_off, adsr_envelope.clone(), max_frame_size)
+                });
+            }
+            EngineEvent::SoftPedal(soft) => self.pedal_state.set_soft(soft),
+            EngineEvent::AllNotesOff => {
+                let (tone_handling, adsr_envelope) = (&mut self.tone_handling, &self.adsr_envelope);
+                let max_frame_size = self.max_frame_size;
+                self.pedal_state.all_notes_off(|note_off| {
+                    tone_handling.add_note_msg(note_off, adsr_envelope.clone(), max_frame_size)
+                });
+            }
+            EngineEvent::AllSoundOff => {
+                self.pedal_state.reset();
+                self.tone_handling.clear_tones();
+            }
+            EngineEvent::ModWheel(mod_wheel) => self.mod_wheel = mod_wheel.clamp(0.0, 1.0),
+            EngineEvent::Aftertouch(aftertouch) => self.aftertouch = aftertouch.clamp(0.0, 1.0),
+            EngineEvent::PartConfig(config) => self.config = config,
+        }
+    }
+
+    /// render one block, output_l.len() must not exceed max_frame_size
+    pub fn process_block(&mut self, output_l: &mut [f32], output_r: &mut [f32]) {
+        let frame_size = output_l.len();
+        let semitones = self.pitch_bend_current * self.ctrl_msg.pitch_bend_range
+            + self.get_vibrato_depth() * self.vibrato_phase.sin();
+        self.tone_handling
+            .set_freq_ratio(2f32.powf(semitones / 12.0));
+        let multiply_out_l = &mut self.multiply_out_l[..frame_size];
+        let multiply_out_r = &mut self.multiply_out_r[..frame_size];
+        multiply_out_l.fill(1.0);
+        multiply_out_r.fill(1.0);
+        let effect_in_l = &mut self.effect_in_l[..frame_size];
+        let effect_in_r = &mut self.effect_in_r[..frame_size];
+
+        self.tone_handling.process_tones(
+            &self.ctrl_msg,
+            effect_in_l,
+            effect_in_r,
+            multiply_out_l,
+            multiply_out_r,
+            frame_size,
+        );
+        self.tone_handling.normalize_out(
+            effect_in_l,
+            effect_in_r,
+            multiply_out_l,
+            multiply_out_r,
+            frame_size,
+        );
+        apply_stereo_width(effect_in_l, effect_in_r, self.ctrl_msg.stereo_width);
+
+        // every effect reads the output of the previous one
+        let effect_out_l = &mut self.effect_out_l[..frame_size];
+        let effect_out_r = &mut self.effect_out_r[..frame_size];
+        for effect in self.effect_chain.iter_mut() {
+            effect.process_samples(
+                Some(&*effect_in_l),
+                Some(&*effect_in_r),
+                Some(&mut *effect_out_l),
+                Some(&mut *effect_out_r),
+            );
+            effect_in_l.copy_from_slice(effect_out_l);
+            effect_in_r.copy_from_slice(effect_out_r);
+        }
+        output_l.copy_from_slice(effect_in_l);
+        output_r.copy_from_slice(effect_in_r);
+
+        self.smooth_pitch_bend(frame_size);
+        self.advance_vibrato(frame_size);
+    }
+
+    /// vibrato depth in semitones from the stronger of mod wheel and aftertouch
+    fn get_vibrato_depth(&self) -> f32 {
+        self.mod_wheel.max(self.aftertouch) * self.ctrl_msg.vibrato_depth
+    }
+
+    fn advance_vibrato(&mut self, block_size: usize) {
+        self.vibrato_phase = (self.vibrato_phase
+            + TAU * self.ctrl_msg.vibrato_rate * block_size as f32 / self.sample_rate)
+            % TAU;
+    }
+
+    fn smooth_pitch_bend(&mut self, block_size: usize) {
+        let coef = 1.0 - (-(block_size as f32) / (PITCH_BEND_SMOOTH_TIME * self.sample_rate)).exp();
+        self.pitch_bend_current += (self.pitch_bend - self.pitch_bend_current) * coef;
+        if (self.pitch_bend - self.pitch_bend_current).abs() < 1e-4 {
+            self.pitch_bend_current = self.pitch_bend;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use crate::adsr::Adsr;
+use crate::ctrl_msg::{CtrlMsg, ParameterMap};
+use crate::equalizer::{default_eq_bands, EqBand};
+use crate::stereo::PanMode;
+
+/// All sound parameters of the oscillator, stored as json.
+/// Missing entries fall back to the defaults of the GUI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Patch {
+    pub volume: f32,
+    pub intensity_am: f32,
+    pub freq_am: f32,
+    pub phase_am: f32,
+    pub intensity_fm: f32,
+    pub freq_fm: f32,
+    pub phase_fm: f32,
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    pub length: usize,
+    pub pan: f32,
+    pub pan_spread: f32,
+    pub pan_mode: PanMode,
+    pub stereo_width: f32,
+    pub pitch_bend_range: f32,
+    pub vibrato_rate: f32,
+    pub vibrato_depth: f32,
+    pub overdrive_toggle: bool,
+    pub overdrive: f32,
+    pub equalizer_toggle: bool,
+    pub equalizer_bands: Vec<EqBand>,
+    pub autopan_toggle: bool,
+    pub autopan_rate: f32,
+    pub autopan_depth: f32,
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        Patch {
+            volume: 1.0,
+            intensity_am: 1.0,
+            freq_am: 0.0,
+            phase_am: 0.0,
+            intensity_fm: 1.0,
+            freq_fm: 0.0,
+            phase_fm: 0.0,
+            attack: 0.1,
+            decay: 0.2,
+            sustain: 0.3,
+            release: 0.2,
+            length: 96000,
+            pan: 0.0,
+            pan_spread: 0.0,
+            pan_mode: PanMode::NoteNumber,
+            stereo_width: 1.0,
+            pitch_bend_range: 2.0,
+            vibrato_rate: 5.0,
+            vibrato_depth: 0.5,
+            overdrive_toggle: false,
+            overdrive: 1.0,
+            equalizer_toggle: false,
+            equalizer_bands: default_eq_bands(),
+            autopan_toggle: false,
+            autopan_rate: 1.0,
+            autopan_depth: 1.0,
+        }
+    }
+}
+
+impl Patch {
+    pub fn to_adsr(&self) -> Adsr {
+        Adsr::new(self.attack, self.decay, self.sustain, self.release)
+    }
+
+    pub fn to_effect_params(&self) -> ParameterMap {
+        let mut map: ParameterMap = HashMap::new();
+        if self.overdrive_toggle {
+            map.insert(
+                "overdrive".to_string(),
+                vec![
+                    format!("gain {}", self.overdrive),
+                    format!("bypass {}", false),
+                ],
+            );
+        } else {
+            map.insert("overdrive".to_string(), vec![format!("bypass {}", true)]);
+        }
+        if self.equalizer_toggle {
+            let mut equalizer_params: Vec<String> = self
+                .equalizer_bands
+                .iter()
+                .enumerate()
+                .map(|(index, band)| band.to_param(index))
+                .collect();
+            equalizer_params.push(format!("bypass {}", false));
+            map.insert("equalizer".to_string(), equalizer_params);
+        } else {
+            map.insert("equalizer".to_string(), vec![format!("bypass {}", true)]);
+        }
+        if self.autopan_toggle {
+            map.insert(
+                "autopan".to_string(),
+                vec![
+                    format!("rate {}", self.autopan_rate),
+                    format!("depth {}", self.autopan_depth),
+                    format!("bypass {}", false),
+                ],
+            );
+        } else {
+            map.insert("autopan".to_string(), vec![format!("bypass {}", true)]);
+        }
+        map
+    }
+
+    pub fn to_ctrl_msg(&self) -> CtrlMsg {
+        CtrlMsg {
+            size: 1024,
+            intensity_am: self.intensity_am,
+            freq_am: self.freq_am,
+            phase_am: self.phase_am,
+            intensity_fm: self.intensity_fm,
+            freq_fm: self.freq_fm,
+            phase_fm: self.phase_fm,
+            volume: self.volume,
+            pan: self.pan,
+            pan_spread: self.pan_spread,
+            pan_mode: self.pan_mode,
+            stereo_width: self.stereo_width,
+            pitch_bend_range: self.pitch_bend_range,
+            vibrato_rate: self.vibrato_rate,
+            vibrato_depth: self.vibrato_depth,
+            effect_params: Some(self.to_effect_params()),
+        }
+    }
+}
+
+pub fn parse_json_file_to_patch(file_path_str: &str) -> Result<Patch, String> {
+    let mut file_content =
+        File::open(file_path_str).map_err(|err| format!("Could not read the json file {}", err))?;
+    let mut contents = String::new();
+    file_content
+        .read_to_string(&mut contents)
+        .map_err(|err| format!("Could not read file to string {}", err))?;
+    let patch: Patch = serde_json::from_str(contents.as_str())
+        .map_err(|err| format!("error in json deserialize {}", err))?;
+    Ok(patch)
+}
+
+pub fn write_patch_to_json_file(patch: &Patch, file_path_str: &str) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(patch)
+        .map_err(|err| format!("error in json serialize {}", err))?;
+    std::fs::write(file_path_str, contents)
+        .map_err(|err| format!("Could not write the json file {}", err))
+}
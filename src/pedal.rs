@@ -0,0 +1,280 @@
+use crate::trigger_note_msg::{NoteType, TriggerNoteMsg};
+
+/// velocity factor while the soft pedal is down
+const SOFT_PEDAL_VELOCITY: f32 = 0.6;
+/// notes that fit without reallocation in the real-time thread
+const MAX_TRACKED_NOTES: usize = 128;
+
+/// Sustain, sostenuto and soft pedal: note offs of sustained notes are held back
+/// until the pedal that keeps them is released.
+#[derive(Debug)]
+pub struct PedalState {
+    sustain: bool,
+    sostenuto: bool,
+    soft: bool,
+    /// keys that are down
+    held_notes: Vec<TriggerNoteMsg>,
+    /// keys that were down when the sostenuto pedal was pressed
+    sostenuto_notes: Vec<f32>,
+    deferred_note_offs: Vec<TriggerNoteMsg>,
+}
+
+impl Default for PedalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PedalState {
+    pub fn new() -> Self {
+        PedalState {
+            sustain: false,
+            sostenuto: false,
+            soft: false,
+            held_notes: Vec::with_capacity(MAX_TRACKED_NOTES),
+            sostenuto_notes: Vec::with_capacity(MAX_TRACKED_NOTES),
+            deferred_note_offs: Vec::with_capacity(MAX_TRACKED_NOTES),
+        }
+    }
+
+    pub fn get_sustain(&self) -> bool {
+        self.sustain
+    }
+
+    pub fn get_sostenuto(&self) -> bool {
+        self.sostenuto
+    }
+
+    pub fn get_soft(&self) -> bool {
+        self.soft
+    }
+
+    pub fn note_on(&mut self, mut trigger_msg: TriggerNoteMsg) -> TriggerNoteMsg {
+        if self.soft {
+            trigger_msg.velocity *= SOFT_PEDAL_VELOCITY;
+        }
+        // a new note on replaces the held back note off of the same key
+        self.deferred_note_offs
+            .retain(|note_off| note_off.freq != trigger_msg.freq);
+        self.held_notes.retain(|note| note.freq != trigger_msg.freq);
+        if self.held_notes.len() < MAX_TRACKED_NOTES {
+            self.held_notes.push(trigger_msg);
+        }
+        trigger_msg
+    }
+
+    /// None if the note off is held back by a pedal
+    pub fn note_off(&mut self, trigger_msg: TriggerNoteMsg) -> Option<TriggerNoteMsg> {
+        self.held_notes.retain(|note| note.freq != trigger_msg.freq);
+        if self.is_sustained(trigger_msg.freq) {
+            if self.deferred_note_offs.len() < MAX_TRACKED_NOTES {
+                self.deferred_note_offs.push(trigger_msg);
+            }
+            return None;
+        }
+        Some(trigger_msg)
+    }
+
+    pub fn set_sustain(&mut self, sustain: bool, release: impl FnMut(TriggerNoteMsg)) {
+        self.sustain = sustain;
+        if !sustain {
+            self.release_deferred(release);
+        }
+    }
+
+    pub fn set_sostenuto(&mut self, sostenuto: bool, release: impl FnMut(TriggerNoteMsg)) {
+        if sostenuto && !self.sostenuto {
+            self.sostenuto_notes.clear();
+            self.sostenuto_notes
+                .extend(self.held_notes.iter().map(|note| note.freq));
+        }
+        self.sostenuto = sostenuto;
+        if !sostenuto {
+            self.sostenuto_notes.clear();
+            self.release_deferred(release);
+        }
+    }
+
+    pub fn set_soft(&mut self, soft: bool) {
+        self.soft = soft;
+    }
+
+    /// note offs for all held notes, the pedals stay as they are.
+    /// Like a released key, notes kept by a pedal sound until the pedal is released (MIDI 1.0).
+    pub fn all_notes_off(&mut self, mut release: impl FnMut(TriggerNoteMsg)) {
+        for index in 0..self.held_notes.len() {
+            let note_off = TriggerNoteMsg {
+                note_type: NoteType::NoteOff,
+                velocity: 0.0,
+                ..self.held_notes[index]
+            };
+            if self.is_sustained(note_off.freq) && self.deferred_note_offs.len() < MAX_TRACKED_NOTES
+            {
+                self.deferred_note_offs.push(note_off);
+            } else {
+                release(note_off);
+            }
+        }
+        self.held_notes.clear();
+    }
+
+    /// forget all notes and pedals
+    pub fn reset(&mut self) {
+        self.sustain = false;
+        self.sostenuto = false;
+        self.soft = false;
+        self.held_notes.clear();
+        self.sostenuto_notes.clear();
+        self.deferred_note_offs.clear();
+    }
+
+    fn is_sustained(&self, freq: f32) -> bool {
+        self.sustain || (self.sostenuto && self.sostenuto_notes.contains(&freq))
+    }
+
+    fn release_deferred(&mut self, mut release: impl FnMut(TriggerNoteMsg)) {
+        let mut index = 0;
+        while index < self.deferred_note_offs.len() {
+            let freq = self.deferred_note_offs[index].freq;
+            if self.is_sustained(freq) {
+                index += 1;
+            } else {
+                release(self.deferred_note_offs.remove(index));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(note_type: NoteType, freq: f32) -> TriggerNoteMsg {
+        TriggerNoteMsg {
+            note_type,
+            freq,
+            velocity: 1.0,
+            length: 48000,
+        }
+    }
+
+    /// frequencies of the note offs a pedal change releases
+    fn released(change: impl FnOnce(&mut dyn FnMut(TriggerNoteMsg))) -> Vec<f32> {
+        let mut freqs = Vec::new();
+        change(&mut |note_off: TriggerNoteMsg| {
+            assert_eq!(note_off.note_type, NoteType::NoteOff);
+            freqs.push(note_off.freq);
+        });
+        freqs
+    }
+
+    #[test]
+    fn sustain_defers_note_offs() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.set_sustain(true, |_| panic!("pedal down releases nothing"));
+        assert!(pedal_state
+            .note_off(note(NoteType::NoteOff, 440.0))
+            .is_none());
+        // notes played while the pedal is down are sustained too
+        pedal_state.note_on(note(NoteType::NoteOn, 660.0));
+        assert!(pedal_state
+            .note_off(note(NoteType::NoteOff, 660.0))
+            .is_none());
+        let freqs = released(|release| pedal_state.set_sustain(false, release));
+        assert_eq!(freqs, vec![440.0, 660.0]);
+        assert!(pedal_state
+            .note_off(note(NoteType::NoteOff, 440.0))
+            .is_some());
+    }
+
+    #[test]
+    fn note_on_replaces_deferred_note_off() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.set_sustain(true, |_| ());
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.note_off(note(NoteType::NoteOff, 440.0));
+        // the key is struck again and still held when the pedal goes up
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        let freqs = released(|release| pedal_state.set_sustain(false, release));
+        assert!(freqs.is_empty());
+    }
+
+    #[test]
+    fn sostenuto_keeps_only_held_notes() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.set_sostenuto(true, |_| ());
+        // played after the pedal went down, not kept
+        pedal_state.note_on(note(NoteType::NoteOn, 660.0));
+        assert!(pedal_state
+            .note_off(note(NoteType::NoteOff, 660.0))
+            .is_some());
+        assert!(pedal_state
+            .note_off(note(NoteType::NoteOff, 440.0))
+            .is_none());
+        let freqs = released(|release| pedal_state.set_sostenuto(false, release));
+        assert_eq!(freqs, vec![440.0]);
+    }
+
+    #[test]
+    fn sostenuto_and_sustain() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.set_sostenuto(true, |_| ());
+        pedal_state.set_sustain(true, |_| ());
+        pedal_state.note_on(note(NoteType::NoteOn, 660.0));
+        pedal_state.note_off(note(NoteType::NoteOff, 440.0));
+        pedal_state.note_off(note(NoteType::NoteOff, 660.0));
+        // the sostenuto pedal still keeps 440
+        let freqs = released(|release| pedal_state.set_sustain(false, release));
+        assert_eq!(freqs, vec![660.0]);
+        let freqs = released(|release| pedal_state.set_sostenuto(false, release));
+        assert_eq!(freqs, vec![440.0]);
+    }
+
+    #[test]
+    fn soft_pedal_lowers_velocity() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.set_soft(true);
+        let note_on = pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        assert_eq!(note_on.velocity, SOFT_PEDAL_VELOCITY);
+    }
+
+    #[test]
+    fn all_notes_off_releases_held_notes() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.note_on(note(NoteType::NoteOn, 660.0));
+        let freqs = released(|release| pedal_state.all_notes_off(release));
+        assert_eq!(freqs, vec![440.0, 660.0]);
+        let freqs = released(|release| pedal_state.all_notes_off(release));
+        assert!(freqs.is_empty());
+    }
+
+    #[test]
+    fn all_notes_off_keeps_sustained_notes() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.set_sustain(true, |_| ());
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.note_on(note(NoteType::NoteOn, 550.0));
+        pedal_state.note_off(note(NoteType::NoteOff, 550.0));
+        let freqs = released(|release| pedal_state.all_notes_off(release));
+        assert!(freqs.is_empty());
+        assert!(pedal_state.get_sustain());
+        let freqs = released(|release| pedal_state.set_sustain(false, release));
+        assert_eq!(freqs, vec![550.0, 440.0]);
+    }
+
+    #[test]
+    fn all_notes_off_keeps_sostenuto_notes() {
+        let mut pedal_state = PedalState::new();
+        pedal_state.note_on(note(NoteType::NoteOn, 440.0));
+        pedal_state.set_sostenuto(true, |_| ());
+        pedal_state.note_on(note(NoteType::NoteOn, 660.0));
+        let freqs = released(|release| pedal_state.all_notes_off(release));
+        assert_eq!(freqs, vec![660.0]);
+        let freqs = released(|release| pedal_state.set_sostenuto(false, release));
+        assert_eq!(freqs, vec![440.0]);
+    }
+}
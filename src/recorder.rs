@@ -0,0 +1,159 @@
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::wav::{SampleFormat, WavSpec, WavWriter};
+
+pub const RECORD_CHUNK_FRAMES: usize = 256;
+const RECORD_QUEUE_CHUNKS: usize = 1024;
+
+//a fixed size container to copy audio out of real-time thread
+#[derive(Copy, Clone)]
+pub struct RecordChunk {
+    pub len: usize,
+    pub data_l: [f32; RECORD_CHUNK_FRAMES],
+    pub data_r: [f32; RECORD_CHUNK_FRAMES],
+}
+
+/// Real-time side of the recorder, lives in the audio callback.
+/// Never blocks or allocates, chunks are dropped if the writer thread can't keep up.
+#[derive(Clone)]
+pub struct RecorderTap {
+    tx_chunk: Sender<RecordChunk>,
+    recording: Arc<AtomicBool>,
+    sample_rate: Arc<AtomicU32>,
+    dropped_chunks: Arc<AtomicUsize>,
+}
+
+impl RecorderTap {
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn push(&self, input_l: &[f32], input_r: &[f32]) {
+        if !self.recording.load(Ordering::Acquire) {
+            return;
+        }
+        let num_frames = input_l.len().min(input_r.len());
+        let mut start = 0;
+        while start < num_frames {
+            let len = (num_frames - start).min(RECORD_CHUNK_FRAMES);
+            let mut chunk = RecordChunk {
+                len,
+                data_l: [0.0; RECORD_CHUNK_FRAMES],
+                data_r: [0.0; RECORD_CHUNK_FRAMES],
+            };
+            chunk.data_l[..len].copy_from_slice(&input_l[start..start + len]);
+            chunk.data_r[..len].copy_from_slice(&input_r[start..start + len]);
+            if self.tx_chunk.try_send(chunk).is_err() {
+                self.dropped_chunks.fetch_add(1, Ordering::Relaxed);
+            }
+            start += len;
+        }
+    }
+}
+
+/// Control side of the recorder, owns the thread writing the wav file
+pub struct Recorder {
+    rx_chunk: Receiver<RecordChunk>,
+    recording: Arc<AtomicBool>,
+    sample_rate: Arc<AtomicU32>,
+    dropped_chunks: Arc<AtomicUsize>,
+    writer_thread: Option<std::thread::JoinHandle<Result<u32, String>>>,
+}
+
+impl Recorder {
+    pub fn new() -> (Recorder, RecorderTap) {
+        let (tx_chunk, rx_chunk) = bounded(RECORD_QUEUE_CHUNKS);
+        let recording = Arc::new(AtomicBool::new(false));
+        let sample_rate = Arc::new(AtomicU32::new(48000));
+        let dropped_chunks = Arc::new(AtomicUsize::new(0));
+        let recorder_tap = RecorderTap {
+            tx_chunk,
+            recording: recording.clone(),
+            sample_rate: sample_rate.clone(),
+            dropped_chunks: dropped_chunks.clone(),
+        };
+        let recorder = Recorder {
+            rx_chunk,
+            recording,
+            sample_rate,
+            dropped_chunks,
+            writer_thread: None,
+        };
+        (recorder, recorder_tap)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.writer_thread.is_some()
+    }
+
+    pub fn get_dropped_chunks(&self) -> usize {
+        self.dropped_chunks.load(Ordering::Relaxed)
+    }
+
+    /// start writing everything pushed into the tap as stereo wav file
+    pub fn start(
+        &mut self,
+        file_path_str: &str,
+        sample_format: SampleFormat,
+    ) -> Result<(), String> {
+        if self.is_recording() {
+            return Err("recorder is already running".to_string());
+        }
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: self.sample_rate.load(Ordering::Relaxed),
+            sample_format,
+        };
+        let mut wav_writer = WavWriter::create(file_path_str, spec)?;
+        // throw away leftovers of the last recording
+        while self.rx_chunk.try_recv().is_ok() {}
+        self.dropped_chunks.store(0, Ordering::Relaxed);
+
+        let rx_chunk = self.rx_chunk.clone();
+        let recording = self.recording.clone();
+        recording.store(true, Ordering::Release);
+        self.writer_thread = Some(std::thread::spawn(move || {
+            loop {
+                match rx_chunk.recv_timeout(Duration::from_millis(50)) {
+                    Ok(chunk) => wav_writer
+                        .write_frames(&[&chunk.data_l[..chunk.len], &chunk.data_r[..chunk.len]])?,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if !recording.load(Ordering::Acquire) {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            while let Ok(chunk) = rx_chunk.try_recv() {
+                wav_writer
+                    .write_frames(&[&chunk.data_l[..chunk.len], &chunk.data_r[..chunk.len]])?;
+            }
+            wav_writer.finalize()?;
+            Ok(wav_writer.get_num_frames())
+        }));
+        Ok(())
+    }
+
+    /// stop recording, returns the number of written frames
+    pub fn stop(&mut self) -> Result<u32, String> {
+        self.recording.store(false, Ordering::Release);
+        match self.writer_thread.take() {
+            Some(writer_thread) => writer_thread
+                .join()
+                .map_err(|_| "wav writer thread panicked".to_string())?,
+            None => Err("recorder is not running".to_string()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.is_recording() {
+            let _ = self.stop();
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// frames kept in the ring, about 1.3 s at 48 kHz
+pub const SCOPE_RING_FRAMES: usize = 65536;
+
+/// Lock-free ring of the last stereo output frames. One writer, any number of readers,
+/// samples are stored as f32 bits in atomics and the oldest are overwritten.
+struct ScopeRing {
+    data_l: Box<[AtomicU32]>,
+    data_r: Box<[AtomicU32]>,
+    /// frames written so far
+    write_pos: AtomicUsize,
+    sample_rate: AtomicU32,
+}
+
+impl ScopeRing {
+    fn new() -> Self {
+        let new_data = || (0..SCOPE_RING_FRAMES).map(|_| AtomicU32::new(0)).collect();
+        ScopeRing {
+            data_l: new_data(),
+            data_r: new_data(),
+            write_pos: AtomicUsize::new(0),
+            sample_rate: AtomicU32::new(48000),
+        }
+    }
+}
+
+/// Real-time side of the scope, lives in the audio callback. Never blocks or allocates.
+#[derive(Clone)]
+pub struct ScopeTap {
+    ring: Arc<ScopeRing>,
+}
+
+impl ScopeTap {
+    pub fn set_sample_rate(&self, sample_rate: u32) {
+        self.ring.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    pub fn push(&self, input_l: &[f32], input_r: &[f32]) {
+        let ring = &self.ring;
+        let write_pos = ring.write_pos.load(Ordering::Relaxed);
+        for (frame, (sample_l, sample_r)) in input_l.iter().zip(input_r).enumerate() {
+            let index = (write_pos + frame) % SCOPE_RING_FRAMES;
+            ring.data_l[index].store(sample_l.to_bits(), Ordering::Relaxed);
+            ring.data_r[index].store(sample_r.to_bits(), Ordering::Relaxed);
+        }
+        let num_frames = input_l.len().min(input_r.len());
+        ring.write_pos
+            .store(write_pos.wrapping_add(num_frames), Ordering::Release);
+    }
+}
+
+/// Gui side of the scope.
+#[derive(Clone)]
+pub struct Scope {
+    ring: Arc<ScopeRing>,
+}
+
+impl Scope {
+    pub fn new() -> (Scope, ScopeTap) {
+        let ring = Arc::new(ScopeRing::new());
+        (Scope { ring: ring.clone() }, ScopeTap { ring })
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.ring.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// the last num_frames frames, fewer if the ring holds less;
+    /// empty if the writer overwrote them while reading
+    pub fn get_latest(&self, num_frames: usize, output_l: &mut Vec<f32>, output_r: &mut Vec<f32>) {
+        output_l.clear();
+        output_r.clear();
+        let ring = &self.ring;
+        let write_pos = ring.write_pos.load(Ordering::Acquire);
+        let num_frames = num_frames.min(write_pos).min(SCOPE_RING_FRAMES / 2);
+        let start = write_pos.wrapping_sub(num_frames);
+        for frame in 0..num_frames {
+            let index = start.wrapping_add(frame) % SCOPE_RING_FRAMES;
+            output_l.push(f32::from_bits(ring.data_l[index].load(Ordering::Relaxed)));
+            output_r.push(f32::from_bits(ring.data_r[index].load(Ordering::Relaxed)));
+        }
+        // the writer may have wrapped around into the frames just read
+        let written = ring
+            .write_pos
+            .load(Ordering::Acquire)
+            .wrapping_sub(write_pos);
+        if written > SCOPE_RING_FRAMES - num_frames {
+            output_l.clear();
+            output_r.clear();
+        }
+    }
+}
+
+/// Start of a window of window_frames frames that begins with the latest rising crossing
+/// of level, None if no crossing leaves room for the whole window.
+pub fn find_last_trigger(samples: &[f32], level: f32, window_frames: usize) -> Option<usize> {
+    let last_start = samples.len().checked_sub(window_frames)?;
+    (1..=last_start)
+        .rev()
+        .find(|&index| samples[index - 1] < level && samples[index] >= level)
+}
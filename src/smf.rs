@@ -0,0 +1,281 @@
+use std::fs::File;
+use std::io::Read;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    TicksPerQuarter(u16),
+    Smpte {
+        frames_per_second: u8,
+        ticks_per_frame: u8,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SmfEventKind {
+    /// channel message with status byte, 1 or 2 data bytes
+    Midi {
+        len: usize,
+        data: [u8; 3],
+    },
+    /// microseconds per quarter note
+    Tempo(u32),
+    SysEx(Vec<u8>),
+    Meta(u8, Vec<u8>),
+    EndOfTrack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmfEvent {
+    /// absolute position in ticks from the start of the track
+    pub tick: u64,
+    pub kind: SmfEventKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Smf {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Vec<SmfEvent>>,
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn peek_u8(&self) -> Result<u8, String> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| format!("unexpected end of midi file at byte {}", self.pos))
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let byte = self.peek_u8()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.bytes.len() {
+            return Err(format!("unexpected end of midi file at byte {}", self.pos));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, String> {
+        let slice = self.read_slice(2)?;
+        Ok(u16::from_be_bytes([slice[0], slice[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let slice = self.read_slice(4)?;
+        Ok(u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]]))
+    }
+
+    /// variable length quantity, at most 4 bytes
+    fn read_vlq(&mut self) -> Result<u32, String> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let byte = self.read_u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format!(
+            "variable length value too long at byte {}",
+            self.pos
+        ))
+    }
+}
+
+/// number of data bytes following a channel status byte
+fn channel_msg_data_len(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+impl Smf {
+    pub fn parse(bytes: &[u8]) -> Result<Smf, String> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.read_slice(4)? != b"MThd" {
+            return Err("not a standard midi file, MThd missing".to_string());
+        }
+        let header_len = reader.read_u32()? as usize;
+        if header_len < 6 {
+            return Err(format!("midi file header too short: {}", header_len));
+        }
+        let format = reader.read_u16()?;
+        let num_tracks = reader.read_u16()?;
+        let raw_division = reader.read_u16()?;
+        reader.read_slice(header_len - 6)?;
+        if format > 1 {
+            return Err(format!("midi file format {} is not supported", format));
+        }
+        let division = if raw_division & 0x8000 == 0 {
+            Division::TicksPerQuarter(raw_division)
+        } else {
+            // negative frames per second in the high byte, 29 stands for 29.97 drop frame
+            let frames_per_second = ((raw_division >> 8) as u8 as i8).wrapping_neg() as u8;
+            if !matches!(frames_per_second, 24 | 25 | 29 | 30) {
+                return Err(format!(
+                    "midi file smpte format {:#06x} is not supported",
+                    raw_division
+                ));
+            }
+            Division::Smpte {
+                frames_per_second,
+                ticks_per_frame: (raw_division & 0xff) as u8,
+            }
+        };
+
+        let mut tracks = Vec::with_capacity(num_tracks as usize);
+        while tracks.len() < num_tracks as usize && !reader.is_empty() {
+            let chunk_type = reader.read_slice(4)?;
+            let chunk_len = reader.read_u32()? as usize;
+            let chunk = reader.read_slice(chunk_len)?;
+            // unknown chunks have to be skipped
+            if chunk_type == b"MTrk" {
+                tracks.push(Smf::parse_track(chunk)?);
+            }
+        }
+        Ok(Smf {
+            format,
+            division,
+            tracks,
+        })
+    }
+
+    pub fn from_file(file_path_str: &str) -> Result<Smf, String> {
+        let mut file_content = File::open(file_path_str)
+            .map_err(|err| format!("Could not read the midi file {}", err))?;
+        let mut contents: Vec<u8> = Vec::new();
+        file_content
+            .read_to_end(&mut contents)
+            .map_err(|err| format!("Could not read midi file {}", err))?;
+        Smf::parse(&contents)
+    }
+
+    fn parse_track(chunk: &[u8]) -> Result<Vec<SmfEvent>, String> {
+        let mut reader = ByteReader::new(chunk);
+        let mut events = Vec::new();
+        let mut tick: u64 = 0;
+        let mut running_status: Option<u8> = None;
+        while !reader.is_empty() {
+            tick += reader.read_vlq()? as u64;
+            let status = match reader.peek_u8()? {
+                byte if byte & 0x80 != 0 => {
+                    reader.read_u8()?;
+                    byte
+                }
+                _ => running_status.ok_or("data byte without running status")?,
+            };
+            let kind = match status {
+                0xff => {
+                    running_status = None;
+                    let meta_type = reader.read_u8()?;
+                    let len = reader.read_vlq()? as usize;
+                    let data = reader.read_slice(len)?;
+                    match meta_type {
+                        0x2f => SmfEventKind::EndOfTrack,
+                        0x51 if len == 3 => SmfEventKind::Tempo(
+                            ((data[0] as u32) << 16) | ((data[1] as u32) << 8) | data[2] as u32,
+                        ),
+                        _ => SmfEventKind::Meta(meta_type, data.to_vec()),
+                    }
+                }
+                0xf0 | 0xf7 => {
+                    running_status = None;
+                    let len = reader.read_vlq()? as usize;
+                    let mut data = Vec::with_capacity(len + 1);
+                    if status == 0xf0 {
+                        data.push(0xf0);
+                    }
+                    data.extend_from_slice(reader.read_slice(len)?);
+                    SmfEventKind::SysEx(data)
+                }
+                0x80..=0xef => {
+                    running_status = Some(status);
+                    let data_len = channel_msg_data_len(status);
+                    let mut data = [status, 0, 0];
+                    data[1..=data_len].copy_from_slice(reader.read_slice(data_len)?);
+                    SmfEventKind::Midi {
+                        len: data_len + 1,
+                        data,
+                    }
+                }
+                _ => return Err(format!("invalid status byte {:#x} in track", status)),
+            };
+            let end_of_track = kind == SmfEventKind::EndOfTrack;
+            events.push(SmfEvent { tick, kind });
+            if end_of_track {
+                break;
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// header and one empty track
+    fn smf_bytes(raw_division: u16) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&raw_division.to_be_bytes());
+        bytes.extend_from_slice(b"MTrk");
+        bytes.extend_from_slice(&4u32.to_be_bytes());
+        // end of track
+        bytes.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn ticks_per_quarter_header() {
+        let smf = Smf::parse(&smf_bytes(480)).unwrap();
+        assert_eq!(smf.format, 0);
+        assert_eq!(smf.division, Division::TicksPerQuarter(480));
+        assert_eq!(smf.tracks.len(), 1);
+    }
+
+    #[test]
+    fn smpte_header() {
+        for frames_per_second in [24u8, 25, 29, 30] {
+            let raw_division = (((frames_per_second as i8).wrapping_neg() as u8 as u16) << 8) | 40;
+            let smf = Smf::parse(&smf_bytes(raw_division)).unwrap();
+            assert_eq!(
+                smf.division,
+                Division::Smpte {
+                    frames_per_second,
+                    ticks_per_frame: 40,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_smpte_header() {
+        // -128 can not be negated as i8, -23 is no smpte rate
+        for raw_division in [0x8028, 0xe928] {
+            assert!(Smf::parse(&smf_bytes(raw_division)).is_err());
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::f32::consts::PI;
+
+/// floor of the spectrum, also the value of empty bins
+pub const SPECTRUM_MIN_DB: f32 = -140.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpectrumWindow {
+    Rectangular,
+    Hann,
+    /// low leakage, for sidebands far below the carrier
+    BlackmanHarris,
+}
+
+impl SpectrumWindow {
+    pub const ALL: [SpectrumWindow; 3] = [Self::Rectangular, Self::Hann, Self::BlackmanHarris];
+
+    fn coefficients(&self, size: usize) -> Vec<f32> {
+        let n = size as f32;
+        (0..size)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / n;
+                match self {
+                    Self::Rectangular => 1.0,
+                    Self::Hann => 0.5 - 0.5 * x.cos(),
+                    Self::BlackmanHarris => {
+                        0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos()
+                            - 0.01168 * (3.0 * x).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SpectrumWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// In-place radix-2 fft, re and im have the same power of two length.
+pub fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Magnitude spectrum in dB with averaging and peak hold, a full scale sine is 0 dB.
+pub struct SpectrumAnalyzer {
+    size: usize,
+    window: SpectrumWindow,
+    coefficients: Vec<f32>,
+    /// 0.0 shows each frame, towards 1.0 averages over more frames
+    pub averaging: f32,
+    re: Vec<f32>,
+    im: Vec<f32>,
+    /// averaged power of the bins, averaging dB values would bias noise low
+    powers: Vec<f32>,
+    magnitudes_db: Vec<f32>,
+    peaks_db: Vec<f32>,
+}
+
+impl SpectrumAnalyzer {
+    /// size is rounded up to a power of two
+    pub fn new(size: usize, window: SpectrumWindow) -> Self {
+        let size = size.max(2).next_power_of_two();
+        SpectrumAnalyzer {
+            size,
+            window,
+            coefficients: window.coefficients(size),
+            averaging: 0.5,
+            re: vec![0.0; size],
+            im: vec![0.0; size],
+            powers: vec![0.0; size / 2 + 1],
+            magnitudes_db: vec![SPECTRUM_MIN_DB; size / 2 + 1],
+            peaks_db: vec![SPECTRUM_MIN_DB; size / 2 + 1],
+        }
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get_window(&self) -> SpectrumWindow {
+        self.window
+    }
+
+    /// the averaged and peak spectrum start over
+    pub fn set_size(&mut self, size: usize) {
+        *self = SpectrumAnalyzer {
+            averaging: self.averaging,
+            ..SpectrumAnalyzer::new(size, self.window)
+        };
+    }
+
+    pub fn set_window(&mut self, window: SpectrumWindow) {
+        self.window = window;
+        self.coefficients = window.coefficients(self.size);
+    }
+
+    /// frequency of a bin
+    pub fn get_bin_freq(&self, bin: usize, sample_rate: f32) -> f32 {
+        bin as f32 * sample_rate / self.size as f32
+    }
+
+    /// analyze the last size samples, fewer samples are padded with zeros
+    pub fn process(&mut self, samples: &[f32]) {
+        let samples = &samples[samples.len().saturating_sub(self.size)..];
+        for (index, (re, im)) in self.re.iter_mut().zip(self.im.iter_mut()).enumerate() {
+            *re = samples.get(index).copied().unwrap_or(0.0) * self.coefficients[index];
+            *im = 0.0;
+        }
+        fft(&mut self.re, &mut self.im);
+        // coherent gain of the window, one sided spectrum
+        let scale = 2.0 / self.coefficients.iter().sum::<f32>();
+        let averaging = self.averaging.clamp(0.0, 0.99);
+        for (bin, ((power, magnitude_db), peak_db)) in self
+            .powers
+            .iter_mut()
+            .zip(self.magnitudes_db.iter_mut())
+            .zip(self.peaks_db.iter_mut())
+            .enumerate()
+        {
+            let frame_power =
+                (self.re[bin] * self.re[bin] + self.im[bin] * self.im[bin]) * scale * scale;
+            *power = averaging * *power + (1.0 - averaging) * frame_power;
+            *magnitude_db = (10.0 * power.log10()).max(SPECTRUM_MIN_DB);
+            *peak_db = peak_db.max(*magnitude_db);
+        }
+    }
+
+    /// averaged dB of the bins 0..=size/2
+    pub fn get_magnitudes_db(&self) -> &[f32] {
+        &self.magnitudes_db
+    }
+
+    pub fn get_peaks_db(&self) -> &[f32] {
+        &self.peaks_db
+    }
+
+    pub fn reset_peaks(&mut self) {
+        self.peaks_db.fill(SPECTRUM_MIN_DB);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive_dft(input: &[f32]) -> (Vec<f32>, Vec<f32>) {
+        let n = input.len();
+        (0..n)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .fold((0.0f64, 0.0f64), |(re, im), (i, x)| {
+                        let angle = -2.0 * std::f64::consts::PI * (k * i) as f64 / n as f64;
+                        (re + *x as f64 * angle.cos(), im + *x as f64 * angle.sin())
+                    })
+            })
+            .map(|(re, im)| (re as f32, im as f32))
+            .unzip()
+    }
+
+    #[test]
+    fn fft_matches_dft() {
+        for n in [2, 8, 64, 256] {
+            // deterministic pseudo random input
+            let input: Vec<f32> = (0..n)
+                .map(|i| ((i * 7919 + 13) % 101) as f32 / 50.0 - 1.0)
+                .collect();
+            let (dft_re, dft_im) = naive_dft(&input);
+            let mut re = input.clone();
+            let mut im = vec![0.0; n];
+            fft(&mut re, &mut im);
+            for k in 0..n {
+                assert!((re[k] - dft_re[k]).abs() < 1e-3, "n {} bin {}", n, k);
+                assert!((im[k] - dft_im[k]).abs() < 1e-3, "n {} bin {}", n, k);
+            }
+        }
+    }
+
+    #[test]
+    fn full_scale_sine_is_0_db() {
+        let size = 1024;
+        let bin = 100;
+        let samples: Vec<f32> = (0..size)
+            .map(|i| (2.0 * PI * (bin * i) as f32 / size as f32).sin())
+            .collect();
+        for window in SpectrumWindow::ALL {
+            let mut analyzer = SpectrumAnalyzer::new(size, window);
+            analyzer.averaging = 0.0;
+            analyzer.process(&samples);
+            let magnitudes_db = analyzer.get_magnitudes_db();
+            assert!(magnitudes_db[bin].abs() < 0.01, "{}", window);
+            let loudest = magnitudes_db
+                .iter()
+                .enumerate()
+                .fold(
+                    0,
+                    |max, (index, db)| if *db > magnitudes_db[max] { index } else { max },
+                );
+            assert_eq!(loudest, bin, "{}", window);
+        }
+    }
+
+    #[test]
+    fn averaging_is_on_power() {
+        let size = 256;
+        let bin = 10;
+        let sine: Vec<f32> = (0..size)
+            .map(|i| (2.0 * PI * (bin * i) as f32 / size as f32).sin())
+            .collect();
+        let mut analyzer = SpectrumAnalyzer::new(size, SpectrumWindow::Rectangular);
+        analyzer.averaging = 0.5;
+        analyzer.process(&sine);
+        analyzer.process(&vec![0.0; size]);
+        // power of 1.0 averaged twice with silence, 10 * log10(0.25)
+        assert!((analyzer.get_magnitudes_db()[bin] + 6.02).abs() < 0.01);
+        // the peak of the first frame, -3 dB
+        assert!((analyzer.get_peaks_db()[bin] + 3.01).abs() < 0.01);
+    }
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PanMode {
+    NoteNumber,
+    Random,
+}
+
+/// constant power pan law, -3 dB on both channels in the center and unity gain at the sides,
+/// pan from -1.0 (left) to 1.0 (right), returns (gain_l, gain_r)
+#[inline]
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// balance of a stereo signal, unity gain in the center,
+/// the side panned away from falls off with the constant power curve, no gain is above 1.0
+#[inline]
+pub fn balance_gains(pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    let attenuation = (pan.abs() * FRAC_PI_2).cos();
+    if pan < 0.0 {
+        (1.0, attenuation)
+    } else {
+        (attenuation, 1.0)
+    }
+}
+
+/// position of a note in the stereo field, two octaves around middle C span the whole field
+pub fn note_pan_position(freq: f32) -> f32 {
+    let note = 12.0 * (freq / 440.0).log2() + 69.0;
+    ((note - 60.0) / 24.0).clamp(-1.0, 1.0)
+}
+
+/// mid/side width: 0.0 mono, 1.0 unchanged, 2.0 extra wide
+pub fn apply_stereo_width(output_l: &mut [f32], output_r: &mut [f32], width: f32) {
+    if width == 1.0 {
+        return;
+    }
+    for (l, r) in output_l.iter_mut().zip(output_r.iter_mut()) {
+        let mid = 0.5 * (*l + *r);
+        let side = 0.5 * (*l - *r) * width;
+        *l = mid + side;
+        *r = mid - side;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn pan_center_is_minus_3_db() {
+        let (gain_l, gain_r) = pan_gains(0.0);
+        assert_close(gain_l, FRAC_1_SQRT_2);
+        assert_close(gain_r, FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn pan_keeps_power() {
+        for pan in [-1.0, -0.5, 0.0, 0.3, 1.0] {
+            let (gain_l, gain_r) = pan_gains(pan);
+            assert_close(gain_l * gain_l + gain_r * gain_r, 1.0);
+        }
+        let (gain_l, gain_r) = pan_gains(-2.0);
+        assert_close(gain_l, 1.0);
+        assert_close(gain_r, 0.0);
+    }
+
+    #[test]
+    fn balance_never_boosts() {
+        let (gain_l, gain_r) = balance_gains(0.0);
+        assert_close(gain_l, 1.0);
+        assert_close(gain_r, 1.0);
+        let (gain_l, gain_r) = balance_gains(0.5);
+        assert_close(gain_l, FRAC_1_SQRT_2);
+        assert_close(gain_r, 1.0);
+        assert_eq!(balance_gains(-1.0), (1.0, balance_gains(1.0).0));
+        for pan in [-1.0, -0.7, 0.2, 1.0, 2.0] {
+            let (gain_l, gain_r) = balance_gains(pan);
+            assert!(gain_l <= 1.0 && gain_r <= 1.0);
+        }
+    }
+}
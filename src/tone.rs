@@ -9,6 +9,7 @@ pub struct Tone {
     pub note_type: NoteType,
     pub freq: f32,
     pub velocity: f32,
+    pub pan_random: f32,
     pub start_pose: usize,
     pub adsr_envelope: Adsr,
     pub envelope: Option<Vec<f32>>,
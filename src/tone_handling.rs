@@ -1,6 +1,7 @@
 use crate::adsr::Adsr;
 use crate::ctrl_msg::CtrlMsg;
 use crate::jackaudio::SineWaveGenerator;
+use crate::stereo::{note_pan_position, pan_gains, PanMode};
 use crate::tone::Tone;
 use crate::tone_map::ToneMap;
 use crate::trigger_note_msg::{NoteType, TriggerNoteMsg};
@@ -9,6 +10,9 @@ use crate::wave::Wave;
 #[derive(Debug)]
 pub struct ToneHandling {
     tone_map: ToneMap,
+    rng_state: u32,
+    sample_rate: f32,
+    freq_ratio: f32,
 }
 
 impl Default for ToneHandling {
@@ -21,8 +25,34 @@ impl ToneHandling {
     pub fn new() -> Self {
         ToneHandling {
             tone_map: ToneMap::new(),
+            rng_state: 0x1234_5678,
+            sample_rate: 48000.0,
+            freq_ratio: 1.0,
         }
     }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// factor on the frequency of all tones, e.g. from pitch bend
+    pub fn set_freq_ratio(&mut self, freq_ratio: f32) {
+        self.freq_ratio = freq_ratio;
+    }
+
+    /// stop all tones immediately without release
+    pub fn clear_tones(&mut self) {
+        self.tone_map.clear();
+    }
+
+    // xorshift, deterministic random pan positions without an extra dependency
+    fn next_random_pan(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        2.0 * (self.rng_state as f32 / u32::MAX as f32) - 1.0
+    }
+
     pub fn add_note_msg(
         &mut self,
         trigger_msg: TriggerNoteMsg,
@@ -35,12 +65,13 @@ impl ToneHandling {
             note_type: trigger_msg.note_type,
             freq: trigger_msg.freq,
             velocity: trigger_msg.velocity,
+            pan_random: 0.0,
             start_pose: 0,
             adsr_envelope: adsr_envelope.clone(),
             envelope: None,
             last_sustain_value_a: 0.0,
             last_sustain_value_b: 0.0,
-            sine_wave_generator: SineWaveGenerator::new(frame_size, 48000.0),
+            sine_wave_generator: SineWaveGenerator::new(frame_size, self.sample_rate),
         };
 
         //get last sustain value
@@ -51,6 +82,11 @@ impl ToneHandling {
         if let Some(sine_wave_generator) = self.get_sine_wave_generator_of_entry(trigger_msg.freq) {
             tone.sine_wave_generator = sine_wave_generator;
         };
+        // a voice keeps its place in the stereo field from note on to release
+        tone.pan_random = match self.tone_map.get(trigger_msg.freq) {
+            Some(playing_tone) => playing_tone.pan_random,
+            None => self.next_random_pan(),
+        };
         match trigger_msg.note_type {
             NoteType::NoteOn => {
                 tone.envelope = Some(
@@ -100,12 +136,16 @@ impl ToneHandling {
     ) {
         output_l.fill(0.0);
         output_r.fill(0.0);
+        let freq_ratio = self.freq_ratio;
         self.tone_map
             .iterate_over_tones(Box::new(|tone: &mut Tone| {
                 let mut frame_l: Vec<f32> = vec![0.0; frame_size];
                 let mut frame_r: Vec<f32> = vec![0.0; frame_size];
 
-                tone.sine_wave_generator.ctrl(ctrl_msg, tone.freq);
+                // blocks can be shorter than a period when split at event boundaries
+                tone.sine_wave_generator.frame_size = frame_size;
+                tone.sine_wave_generator
+                    .ctrl(ctrl_msg, tone.freq * freq_ratio);
                 tone.sine_wave_generator
                     .process_samples(&mut frame_l, &mut frame_r);
                 match &tone.envelope {
@@ -134,6 +174,14 @@ impl ToneHandling {
                     None => (),
                 }
 
+                let pan_position = match ctrl_msg.pan_mode {
+                    PanMode::NoteNumber => note_pan_position(tone.freq),
+                    PanMode::Random => tone.pan_random,
+                };
+                let (gain_l, gain_r) = pan_gains(ctrl_msg.pan + ctrl_msg.pan_spread * pan_position);
+                frame_l.iter_mut().for_each(|x| *x *= gain_l);
+                frame_r.iter_mut().for_each(|x| *x *= gain_r);
+
                 for index in 0..frame_size {
                     output_l[index] += frame_l[index];
                     output_r[index] += frame_r[index];
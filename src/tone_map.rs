@@ -43,6 +43,10 @@ impl ToneMap {
         self.hm.is_empty()
     }
 
+    pub fn clear(&mut self) {
+        self.hm.clear();
+    }
+
     pub fn print(&self) {
         println!("{:?}", self.hm);
     }
@@ -1,13 +1,33 @@
+use crate::jackmidi::MidiMessage;
+use crate::util::*;
+
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
 pub enum NoteType {
     NoteOn,
     NoteOff,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct TriggerNoteMsg {
     pub note_type: NoteType,
     pub freq: f32,
     pub velocity: f32,
     pub length: usize,
 }
+
+impl TriggerNoteMsg {
+    /// note on/off from raw midi bytes, a note on with velocity 0 is a note off
+    pub fn from_midi_bytes(bytes: &[u8], length: usize) -> Option<Self> {
+        let (note_type, key, velocity) = match MidiMessage::from_bytes(bytes) {
+            MidiMessage::NoteOn { key, velocity, .. } => (NoteType::NoteOn, key, velocity),
+            MidiMessage::NoteOff { key, velocity, .. } => (NoteType::NoteOff, key, velocity),
+            _ => return None,
+        };
+        Some(TriggerNoteMsg {
+            note_type,
+            freq: to_freq_f32(key),
+            velocity: velocity as f32 / 127.0,
+            length,
+        })
+    }
+}
@@ -54,8 +54,33 @@ pub fn from_status_byte(sb: u8) -> (u8, u8) {
     (status, channel)
 }
 
+/// 14 bit pitch bend value to -1.0..=1.0, 8192 is the center
+#[inline]
+pub fn pitch_bend_from_u14(value: u16) -> f32 {
+    let centered = mask14(value) as f32 - 8192.0;
+    if centered < 0.0 {
+        centered / 8192.0
+    } else {
+        centered / 8191.0
+    }
+}
+
+/// RPN 0 value to semitones, semitones in the MSB and cents in the LSB
+#[inline]
+pub fn pitch_bend_range_from_rpn(value: u16) -> f32 {
+    (value >> 7) as f32 + (value & 0x7f) as f32 / 100.0
+}
+
 #[inline(always)]
 pub fn to_freq_f32(value: u8) -> f32 {
     let exp = (f32::from(value) + 36.376_316) / 12.0;
     2f32.powf(exp)
 }
+
+/// nearest midi note of a frequency, inverse of to_freq_f32
+#[inline]
+pub fn freq_to_note(freq: f32) -> u8 {
+    (12.0 * freq.max(1.0).log2() - 36.376_316)
+        .round()
+        .clamp(0.0, 127.0) as u8
+}
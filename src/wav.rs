@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn bytes_per_sample(&self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 2,
+            SampleFormat::Int24 => 3,
+            SampleFormat::Float32 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+}
+
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    spec: WavSpec,
+    num_frames: u32,
+    finalized: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(file_path_str: &str, spec: WavSpec) -> Result<Self, String> {
+        let file = File::create(file_path_str)
+            .map_err(|err| format!("Could not create wav file {}", err))?;
+        WavWriter::new(BufWriter::new(file), spec)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W, spec: WavSpec) -> Result<Self, String> {
+        if spec.channels == 0 {
+            return Err("wav file needs at least one channel".to_string());
+        }
+        let mut wav_writer = WavWriter {
+            writer,
+            spec,
+            num_frames: 0,
+            finalized: false,
+        };
+        wav_writer.write_header()?;
+        Ok(wav_writer)
+    }
+
+    pub fn get_spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    pub fn get_num_frames(&self) -> u32 {
+        self.num_frames
+    }
+
+    /// write one slice per channel, all slices need the same length
+    pub fn write_frames(&mut self, channels: &[&[f32]]) -> Result<(), String> {
+        if channels.len() != self.spec.channels as usize {
+            return Err(format!(
+                "expected {} channels, got {}",
+                self.spec.channels,
+                channels.len()
+            ));
+        }
+        let num_frames = channels
+            .iter()
+            .map(|channel| channel.len())
+            .min()
+            .unwrap_or(0);
+        for index in 0..num_frames {
+            for channel in channels {
+                self.write_sample(channel[index])?;
+            }
+        }
+        self.num_frames += num_frames as u32;
+        Ok(())
+    }
+
+    /// write interleaved samples, length has to be a multiple of the channel count
+    pub fn write_interleaved(&mut self, samples: &[f32]) -> Result<(), String> {
+        let channels = self.spec.channels as usize;
+        if samples.len() % channels != 0 {
+            return Err(format!(
+                "{} interleaved samples do not fit {} channels",
+                samples.len(),
+                channels
+            ));
+        }
+        for sample in samples {
+            self.write_sample(*sample)?;
+        }
+        self.num_frames += (samples.len() / channels) as u32;
+        Ok(())
+    }
+
+    /// patch chunk sizes in the header and flush, has to be called before dropping the writer
+    pub fn finalize(&mut self) -> Result<(), String> {
+        if self.finalized {
+            return Ok(());
+        }
+        self.finalized = true;
+        let data_size = self.data_size();
+        if data_size % 2 == 1 {
+            // chunks are word aligned
+            self.write_bytes(&[0])?;
+        }
+        let riff_size = self.header_size() - 8 + data_size + data_size % 2;
+        self.seek(4)?;
+        self.write_bytes(&riff_size.to_le_bytes())?;
+        if self.spec.sample_format == SampleFormat::Float32 {
+            // sample length of the fact chunk
+            self.seek(self.header_size() as u64 - 12)?;
+            self.write_bytes(&self.num_frames.to_le_bytes())?;
+        }
+        self.seek(self.header_size() as u64 - 4)?;
+        self.write_bytes(&data_size.to_le_bytes())?;
+        self.writer
+            .seek(SeekFrom::End(0))
+            .map_err(|err| format!("Could not seek in wav file {}", err))?;
+        self.writer
+            .flush()
+            .map_err(|err| format!("Could not flush wav file {}", err))
+    }
+
+    fn header_size(&self) -> u32 {
+        match self.spec.sample_format {
+            // RIFF + fmt(16) + data
+            SampleFormat::Int16 | SampleFormat::Int24 => 12 + 24 + 8,
+            // RIFF + fmt(18) + fact + data
+            SampleFormat::Float32 => 12 + 26 + 12 + 8,
+        }
+    }
+
+    fn data_size(&self) -> u32 {
+        self.num_frames * self.block_align() as u32
+    }
+
+    fn block_align(&self) -> u16 {
+        self.spec.channels * self.spec.sample_format.bytes_per_sample()
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let is_float = self.spec.sample_format == SampleFormat::Float32;
+        let bytes_per_sample = self.spec.sample_format.bytes_per_sample();
+        let block_align = self.block_align();
+        let byte_rate = self.spec.sample_rate * block_align as u32;
+        let mut header: Vec<u8> = Vec::with_capacity(self.header_size() as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(self.header_size() - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&(if is_float { 18u32 } else { 16u32 }).to_le_bytes());
+        let format_tag = if is_float {
+            WAVE_FORMAT_IEEE_FLOAT
+        } else {
+            WAVE_FORMAT_PCM
+        };
+        header.extend_from_slice(&format_tag.to_le_bytes());
+        header.extend_from_slice(&self.spec.channels.to_le_bytes());
+        header.extend_from_slice(&self.spec.sample_rate.to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&(bytes_per_sample * 8).to_le_bytes());
+        if is_float {
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(b"fact");
+            header.extend_from_slice(&4u32.to_le_bytes());
+            header.extend_from_slice(&0u32.to_le_bytes());
+        }
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        self.write_bytes(&header)
+    }
+
+    fn write_sample(&mut self, sample: f32) -> Result<(), String> {
+        let sample = sample.clamp(-1.0, 1.0);
+        match self.spec.sample_format {
+            SampleFormat::Int16 => {
+                let value = (sample * i16::MAX as f32).round() as i16;
+                self.write_bytes(&value.to_le_bytes())
+            }
+            SampleFormat::Int24 => {
+                let value = (sample * 8_388_607.0).round() as i32;
+                self.write_bytes(&value.to_le_bytes()[..3])
+            }
+            SampleFormat::Float32 => self.write_bytes(&sample.to_le_bytes()),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|err| format!("Could not write wav file {}", err))
+    }
+
+    fn seek(&mut self, pos: u64) -> Result<(), String> {
+        self.writer
+            .seek(SeekFrom::Start(pos))
+            .map(|_| ())
+            .map_err(|err| format!("Could not seek in wav file {}", err))
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
+
+/// write complete buffers, e.g. the output of `Engine::render`, to a wav file
+pub fn write_wav_file(
+    file_path_str: &str,
+    spec: WavSpec,
+    channels: &[&[f32]],
+) -> Result<(), String> {
+    let mut wav_writer = WavWriter::create(file_path_str, spec)?;
+    wav_writer.write_frames(channels)?;
+    wav_writer.finalize()
+}
@@ -13,6 +13,7 @@ pub struct SineWave {
     num_samples: usize,
     offset: usize,
     fs: f64,
+    phase_base: f64,
 }
 
 impl SineWave {
@@ -41,9 +42,15 @@ impl SineWave {
             num_samples,
             offset,
             fs,
+            phase_base: 0.0,
         }
     }
 
+    /// phase of the carrier at sample 0, keeps the wave continuous when freq_base changes
+    pub fn set_phase_base(&mut self, phase_base: f64) {
+        self.phase_base = phase_base;
+    }
+
     pub fn gen_values(&self) -> (usize, std::vec::Vec<f32>) {
         let modulator_hub: f64 = self.intensity_fm;
         let modulator_freq: f64 = self.freq_fm;
@@ -67,6 +74,7 @@ impl SineWave {
                 (self.amplitude
                     * ((amp(i as f64, self.freq_am, self.fs) + (1.0 - self.intensity_am))
                         * ((2.0 * f64::consts::PI * (self.freq_base / self.fs) * (i as f64)
+                            + self.phase_base
                             + modulator_index * shift(i as f64, self.freq_fm, self.fs))
                         .sin()))) as f32
             })